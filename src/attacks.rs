@@ -0,0 +1,214 @@
+// attacks.rs
+// Precomputed attack tables. Knight, king and pawn attacks are computed at compile time;
+// bishop and rook attacks use magic bitboards, whose tables are built the first time they are needed.
+use std::sync::OnceLock;
+
+use crate::board::side_index;
+
+pub const KNIGHT_ATTACKS: [u64; 64] = leaper_attacks(&[(-2, -1), (-2, 1), (-1, -2), (-1, 2), (1, -2), (1, 2), (2, -1), (2, 1)]);
+pub const KING_ATTACKS: [u64; 64] = leaper_attacks(&[(-1, -1), (-1, 0), (-1, 1), (0, -1), (0, 1), (1, -1), (1, 0), (1, 1)]);
+
+// Squares attacked by a pawn of each side, indexed [white, black]
+pub const PAWN_ATTACKS: [[u64; 64]; 2] = [leaper_attacks(&[(1, -1), (1, 1)]), leaper_attacks(&[(-1, -1), (-1, 1)])];
+
+// (rank, file) steps for each slider
+const BISHOP_DIRECTIONS: [(i8, i8); 4] = [(-1, -1), (-1, 1), (1, -1), (1, 1)];
+const ROOK_DIRECTIONS: [(i8, i8); 4] = [(-1, 0), (0, -1), (0, 1), (1, 0)];
+
+// Builds the attack sets of a piece that jumps by fixed (rank, file) offsets
+const fn leaper_attacks(offsets: &[(i8, i8)]) -> [u64; 64] {
+    let mut attacks = [0u64; 64];
+    let mut square = 0;
+    while square < 64 {
+        let rank = (square / 8) as i8;
+        let file = (square % 8) as i8;
+        let mut i = 0;
+        while i < offsets.len() {
+            let to_rank = rank + offsets[i].0;
+            let to_file = file + offsets[i].1;
+            if to_rank >= 0 && to_rank < 8 && to_file >= 0 && to_file < 8 {
+                attacks[square] |= 1u64 << (to_rank * 8 + to_file);
+            }
+            i += 1;
+        }
+        square += 1;
+    }
+    attacks
+}
+
+// Squares attacked by a pawn of the given side standing on `square`
+pub fn pawn_attacks(square: u8, side: bool) -> u64 {
+    PAWN_ATTACKS[side_index(side)][square as usize]
+}
+
+pub fn knight_attacks(square: u8) -> u64 {
+    KNIGHT_ATTACKS[square as usize]
+}
+
+pub fn king_attacks(square: u8) -> u64 {
+    KING_ATTACKS[square as usize]
+}
+
+pub fn bishop_attacks(square: u8, occupancy: u64) -> u64 {
+    let tables = slider_tables();
+    tables.bishop[square as usize].attacks(&tables.attacks, occupancy)
+}
+
+pub fn rook_attacks(square: u8, occupancy: u64) -> u64 {
+    let tables = slider_tables();
+    tables.rook[square as usize].attacks(&tables.attacks, occupancy)
+}
+
+pub fn queen_attacks(square: u8, occupancy: u64) -> u64 {
+    bishop_attacks(square, occupancy) | rook_attacks(square, occupancy)
+}
+
+// Magic multiplier and the slice of the shared attack table belonging to one square
+#[derive(Clone, Copy, Default)]
+struct Magic {
+    mask: u64,    // Squares whose occupancy can block the slider, board edges excluded
+    magic: u64,
+    shift: u32,
+    offset: usize,
+}
+
+impl Magic {
+    fn index(&self, occupancy: u64) -> usize {
+        self.offset + ((occupancy & self.mask).wrapping_mul(self.magic) >> self.shift) as usize
+    }
+
+    fn attacks(&self, table: &[u64], occupancy: u64) -> u64 {
+        table[self.index(occupancy)]
+    }
+}
+
+struct SliderTables {
+    bishop: [Magic; 64],
+    rook: [Magic; 64],
+    attacks: Vec<u64>,
+}
+
+static SLIDER_TABLES: OnceLock<SliderTables> = OnceLock::new();
+
+const MAGIC_SEEDS: [u64; 8] = [728, 10316, 55013, 32803, 12281, 15100, 16645, 255];
+
+fn slider_tables() -> &'static SliderTables {
+    SLIDER_TABLES.get_or_init(build_slider_tables)
+}
+
+fn build_slider_tables() -> SliderTables {
+    let mut tables = SliderTables {
+        bishop: [Magic::default(); 64],
+        rook: [Magic::default(); 64],
+        attacks: Vec::new(),
+    };
+
+    for square in 0..64 {
+        // Fixed seeds, one per rank, known to find magics after few attempts
+        let seed = MAGIC_SEEDS[square / 8];
+        tables.bishop[square] = find_magic(square as u8, &BISHOP_DIRECTIONS, &mut tables.attacks, &mut XorShift(seed));
+        tables.rook[square] = find_magic(square as u8, &ROOK_DIRECTIONS, &mut tables.attacks, &mut XorShift(seed));
+    }
+
+    tables
+}
+
+// Searches for a magic number that maps every blocker configuration of the square onto
+// a table slot without destructive collisions, and appends that table to `attacks`
+fn find_magic(square: u8, directions: &[(i8, i8)], attacks: &mut Vec<u64>, rng: &mut XorShift) -> Magic {
+    let mask = relevant_occupancy(square, directions);
+    let bits = mask.count_ones();
+    let size = 1usize << bits;
+
+    // Enumerate every subset of the mask with the carry-rippler trick
+    let mut occupancies = Vec::with_capacity(size);
+    let mut reference = Vec::with_capacity(size);
+    let mut subset = 0u64;
+    loop {
+        occupancies.push(subset);
+        reference.push(slow_slider_attacks(square, subset, directions));
+        subset = subset.wrapping_sub(mask) & mask;
+        if subset == 0 {
+            break;
+        }
+    }
+
+    let mut table = vec![0u64; size];
+    // Which attempt last wrote each slot, so the table doesn't need clearing between attempts
+    let mut epoch = vec![0u32; size];
+    let mut attempt = 0;
+    loop {
+        // Sparse random numbers make good magic candidates
+        let magic = rng.next() & rng.next() & rng.next();
+        if (mask.wrapping_mul(magic) >> 56).count_ones() < 6 {
+            continue;
+        }
+
+        let candidate = Magic { mask, magic, shift: 64 - bits, offset: 0 };
+        attempt += 1;
+
+        let mut ok = true;
+        for (occupancy, &attack) in occupancies.iter().zip(&reference) {
+            let index = candidate.index(*occupancy);
+            if epoch[index] != attempt {
+                epoch[index] = attempt;
+                table[index] = attack;
+            } else if table[index] != attack {
+                ok = false;
+                break;
+            }
+        }
+
+        if ok {
+            let offset = attacks.len();
+            attacks.extend_from_slice(&table);
+            return Magic { offset, ..candidate };
+        }
+    }
+}
+
+// The squares a slider attacks from `square`, walking each ray until it hits a blocker
+fn slow_slider_attacks(square: u8, occupancy: u64, directions: &[(i8, i8)]) -> u64 {
+    let mut attacks = 0;
+    for &(rank_step, file_step) in directions {
+        let mut rank = (square / 8) as i8 + rank_step;
+        let mut file = (square % 8) as i8 + file_step;
+        while (0..8).contains(&rank) && (0..8).contains(&file) {
+            let bit = 1u64 << (rank * 8 + file);
+            attacks |= bit;
+            if occupancy & bit != 0 {
+                break;
+            }
+            rank += rank_step;
+            file += file_step;
+        }
+    }
+    attacks
+}
+
+// Like slow_slider_attacks on an empty board, but without the last square of each ray:
+// a piece on the edge never blocks anything further
+fn relevant_occupancy(square: u8, directions: &[(i8, i8)]) -> u64 {
+    let mut mask = 0;
+    for &(rank_step, file_step) in directions {
+        let mut rank = (square / 8) as i8 + rank_step;
+        let mut file = (square % 8) as i8 + file_step;
+        while (0..8).contains(&(rank + rank_step)) && (0..8).contains(&(file + file_step)) {
+            mask |= 1u64 << (rank * 8 + file);
+            rank += rank_step;
+            file += file_step;
+        }
+    }
+    mask
+}
+
+struct XorShift(u64);
+
+impl XorShift {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(2685821657736338717)
+    }
+}
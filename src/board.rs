@@ -1,10 +1,34 @@
 pub use crate::piece::PieceType;
 pub use crate::r#move::Move;
-use crate::utils::{get_rank, get_file};
+use crate::attacks;
+use crate::utils::{get_rank, pop_lsb};
+use crate::zobrist;
 
 pub const WHITE: bool = true;
 pub const BLACK: bool = false;
 
+// Index of a side into tables with an entry per side, White first
+pub fn side_index(side: bool) -> usize {
+    if side == WHITE { 0 } else { 1 }
+}
+
+// Squares of the same colour as h1
+const LIGHT_SQUARES: u64 = 0x55AA55AA55AA55AA;
+
+// The a-file and the first rank; the other files and ranks are these shifted along
+pub const FILE_A: u64 = 0x0101010101010101;
+pub const RANK_1: u64 = 0xFF;
+
+// Every square on a file, 0 being the a-file
+pub fn file_mask(file: u8) -> u64 {
+    FILE_A << file
+}
+
+// Every square on a rank, 0 being the first rank
+pub fn rank_mask(rank: u8) -> u64 {
+    RANK_1 << (rank * 8)
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct Board {
     pub white_pawns: u64,
@@ -26,6 +50,9 @@ pub struct Board {
     pub side_to_move: bool,     // True for white, False for black
     pub halfmove_clock: u8,     // Number of halfmoves since the last capture or pawn advance (for the fifty-move rule)
     pub fullmove_number: u16,   // The number of the full move, it starts at 1, and is incremented after Black's move
+    pub hash: u64,              // Zobrist hash of the position, kept up to date by make_move and unmake_move
+    pub pawn_key: u64,          // Zobrist hash of the pawns alone, for caching pawn structure evaluations
+    pub history: Vec<u64>,      // Hashes of the positions before each move made on this board, oldest first
 
     // implement is_checkmate and is_draw
 
@@ -36,13 +63,14 @@ pub struct Board {
 
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug)]
 pub struct UndoState {
     pub captured_piece: Option<PieceType>, // The piece type that was captured, if any
     pub en_passant: Option<u8>,            // The en passant square, if any
     pub castling_rights: u8,               // The castling rights before the move
     pub halfmove_clock: u8,                // The halfmove clock before the move
     pub fullmove_number: u16,              // The fullmove number before the move
+    pub hash: u64,                         // The Zobrist hash before the move
     pub is_checkmate: bool,
     pub is_draw: bool,
 
@@ -62,7 +90,20 @@ impl Board {
 
     // Clears a square on the bitboards
     pub fn clear_square(&mut self, square: u8) {
-        let mask = !(1u64 << square);
+        let mask = 1u64 << square;
+        if self.occupancy() & mask == 0 {
+            return;
+        }
+
+        // Remove the piece standing here from the hash
+        if let Some((piece_type, side)) = self.piece_at(square) {
+            self.hash ^= zobrist::piece_key(piece_type, side, square);
+            if piece_type == PieceType::Pawn {
+                self.pawn_key ^= zobrist::piece_key(piece_type, side, square);
+            }
+        }
+
+        let mask = !mask;
         
         // Clear square for white pieces
         self.white_pawns &= mask;
@@ -97,45 +138,13 @@ impl Board {
 
     // Promote a pawn
     pub fn promote_pawn(&mut self, square: u8, promotion: PieceType) {
-        // Clear the pawn from the square
-        self.clear_square(square);
-
-        let mask = 1u64 << square;
-        match promotion {
-            PieceType::Queen => {
-                if self.side_to_move == WHITE {
-                    self.white_queens |= mask;
-                } else {
-                    self.black_queens |= mask;
-                }
-            },
-
-            PieceType::Knight => {
-                if self.side_to_move == WHITE {
-                    self.white_knights |= mask;
-                } else {
-                    self.black_knights |= mask;
-                }
-            },
-
-            PieceType::Rook => {
-                if self.side_to_move == WHITE {
-                    self.white_rooks |= mask;
-                } else {
-                    self.black_rooks |= mask;
-                }
-            },
-
-            PieceType::Bishop => {
-                if self.side_to_move == WHITE {
-                    self.white_bishops |= mask;
-                } else {
-                    self.black_bishops |= mask;
-                }
-            },
-
-            _ => panic!("Invalid promotion"),
+        if matches!(promotion, PieceType::Pawn | PieceType::King) {
+            panic!("Invalid promotion");
         }
+
+        // Replace the pawn with the promoted piece
+        self.clear_square(square);
+        self.set_square(square, promotion);
     }
 
     // Handles the specifics of pawn moves, including double moves and en passant captures
@@ -194,10 +203,22 @@ impl Board {
         
     }
 
-    // // Determine if a move is a capture
-    // fn is_capture(&self, mv: &Move) -> bool {
-    //     self.is_occupied_by_opponent(mv.to, self.side_to_move)
-    // }
+    // Determine if a move is a capture, including en passant
+    pub fn is_capture(&self, mv: &Move) -> bool {
+        self.is_occupied_by_opponent(mv.to, self.side_to_move) ||
+            (mv.piece_type == PieceType::Pawn && self.en_passant == Some(mv.to))
+    }
+
+    // The type of piece a move captures, if any
+    pub fn captured_piece_type(&self, mv: &Move) -> Option<PieceType> {
+        if self.is_occupied_by_opponent(mv.to, self.side_to_move) {
+            Some(self.get_piece_type(mv.to))
+        } else if mv.piece_type == PieceType::Pawn && self.en_passant == Some(mv.to) {
+            Some(PieceType::Pawn)
+        } else {
+            None
+        }
+    }
 
     // Handles the specifics of castling moves
     pub fn handle_castling(&mut self, to: u8) {
@@ -236,6 +257,10 @@ impl Board {
     // Sets a square on the bitboards with the specified piece type
     pub fn set_square(&mut self, square: u8, piece_type: PieceType) {
         let mask = 1u64 << square;
+        self.hash ^= zobrist::piece_key(piece_type, self.side_to_move, square);
+        if piece_type == PieceType::Pawn {
+            self.pawn_key ^= zobrist::piece_key(piece_type, self.side_to_move, square);
+        }
 
         match piece_type {
 
@@ -293,15 +318,15 @@ impl Board {
     // Generates all pseudo-legal moves for the current position
     // This function should not filter out moves that leave the king in check
     pub fn generate_pseudo_legal_moves(&mut self) -> Vec<Move> {
-        let mut moves: Vec<Move> = Vec::new();
+        let mut moves: Vec<Move> = Vec::with_capacity(64);
 
         // Generate moves for each piece type
-        moves.append(&mut self.generate_pawn_moves());
-        moves.append(&mut self.generate_knight_moves());
-        moves.append(&mut self.generate_bishop_moves());
-        moves.append(&mut self.generate_rook_moves());
-        moves.append(&mut self.generate_queen_moves());
-        moves.append(&mut self.generate_king_moves());
+        self.generate_pawn_moves(&mut moves);
+        self.generate_knight_moves(&mut moves);
+        self.generate_bishop_moves(&mut moves);
+        self.generate_rook_moves(&mut moves);
+        self.generate_queen_moves(&mut moves);
+        self.generate_king_moves(&mut moves);
 
         moves
     }
@@ -310,9 +335,24 @@ impl Board {
     // This function filters out moves that leave the king in check
     pub fn generate_legal_moves(&mut self) -> Vec<Move> {
         let pseudo_legal_moves = self.generate_pseudo_legal_moves();
-        let mut legal_moves: Vec<Move> = Vec::new();
+        let mut legal_moves: Vec<Move> = Vec::with_capacity(pseudo_legal_moves.len());
+
+        // When not in check, a piece that is not on any line through our king cannot expose it by moving.
+        // Only king moves, en passant and moves of possibly pinned pieces need to be tried on the board.
+        let king = if self.side_to_move == WHITE { self.white_king } else { self.black_king };
+        let king_lines = if king != 0 && !self.is_in_check(self.side_to_move) {
+            attacks::queen_attacks(self.bitboard_to_square(king), 0)
+        } else {
+            u64::MAX
+        };
 
         for mv in pseudo_legal_moves {
+            let is_en_passant = mv.piece_type == PieceType::Pawn && self.en_passant == Some(mv.to);
+            if mv.piece_type != PieceType::King && !is_en_passant && king_lines & (1u64 << mv.from) == 0 {
+                legal_moves.push(mv);
+                continue;
+            }
+
             let undo_state = self.make_move(mv);
             if !self.is_in_check(!self.side_to_move) {
                 legal_moves.push(mv);
@@ -323,70 +363,28 @@ impl Board {
         legal_moves
     }
 
-    // Stub for checking if the current side is in check
+    // Finds the legal move matching a UCI move string such as e2e4 or e7e8q
+    pub fn parse_uci_move(&mut self, input: &str) -> Option<Move> {
+        self.generate_legal_moves().into_iter().find(|mv| mv.get_uci() == input)
+    }
+
+    // Checks if the given side's king is attacked
     pub fn is_in_check(&self, side: bool) -> bool {
         // Find the king's position
-        let king_pos = if side == WHITE {
-            self.bitboard_to_square(self.white_king)
-        } else {
-            self.bitboard_to_square(self.black_king)
-        };
-
-        // Check for attacks from pawns
-        if self.is_attacked_by_pawns(king_pos, side) {
-            return true;
-        }
-
-        // Check for attacks from knights
-        if self.is_attacked_by_knights(king_pos, side) {
-            return true;
-        }
-
-        // Check for attacks from bishops and queens (diagonally)
-        if self.is_attacked_by_sliding_pieces(king_pos, side, &[-9, -7, 7, 9]) {
-            return true;
-        }
-
-        // Check for attacks from rooks and queens (straight lines)
-        if self.is_attacked_by_sliding_pieces(king_pos, side, &[-8, -1, 1, 8]) {
-            return true;
-        }
-
-        // Check for attacks from the opposing king
-        if self.is_attacked_by_king(king_pos, side) {
-            return true;
+        let king = if side == WHITE { self.white_king } else { self.black_king };
+        if king == 0 {
+            return false; // from_fen refuses positions without a king, only a board edited by hand gets here
         }
 
-        false
+        self.is_square_attacked(self.bitboard_to_square(king), side)
     }
 
-    fn is_square_attacked(&self, square: u8, side: bool,) -> bool {
-        // Check for attacks from pawns
-        if self.is_attacked_by_pawns(square, side) {
-            return true;
-        }
-    
-        // Check for attacks from knights
-        if self.is_attacked_by_knights(square, side) {
-            return true;
-        }
-    
-        // Check for attacks from bishops and queens (diagonally)
-        if self.is_attacked_by_sliding_pieces(square, side, &[-9, -7, 7, 9]) {
-            return true;
-        }
-    
-        // Check for attacks from rooks and queens (straight lines)
-        if self.is_attacked_by_sliding_pieces(square, side, &[-8, -1, 1, 8]) {
-            return true;
-        }
-    
-        // Check for attacks from the opposing king
-        if self.is_attacked_by_king(square, side) {
-            return true;
-        }
-    
-        false
+    // Checks if a square is attacked by the opponent of the given side
+    fn is_square_attacked(&self, square: u8, side: bool) -> bool {
+        self.is_attacked_by_pawns(square, side) ||
+            self.is_attacked_by_knights(square, side) ||
+            self.is_attacked_by_sliding_pieces(square, side) ||
+            self.is_attacked_by_king(square, side)
     }
 
     // Helper method to convert a bitboard to a square index
@@ -396,491 +394,147 @@ impl Board {
 
     // Helper method to check for pawn attacks
     fn is_attacked_by_pawns(&self, square: u8, side: bool) -> bool {
-        let pawn_attacks = if side == WHITE {
-            [7, 9] // Black pawn attack offsets
-        } else {
-            [-9, -7] // White pawn attack offsets
-        };
-
-        for &offset in &pawn_attacks {
-            let attack_square = (square as i8).wrapping_add(offset) as u8;
-            if self.is_on_board(attack_square) {
-                let pawn_bitboard = if side == WHITE {
-                    self.black_pawns
-                } else {
-                    self.white_pawns
-                };
-                if pawn_bitboard & (1u64 << attack_square) != 0 {
-                    // only attacking if the attack file is different by 1
-                    let from_file = get_file(square);
-                    let to_file = get_file(attack_square);
-                    if (from_file as i8 - to_file as i8).abs() == 1 {
-                        return true;
-                    }
-                }
-            }
-        }
-
-        false
+        // An enemy pawn attacks the square if it stands where our own pawn on this square would capture
+        let enemy_pawns = if side == WHITE { self.black_pawns } else { self.white_pawns };
+        attacks::pawn_attacks(square, side) & enemy_pawns != 0
     }
 
     // Helper method to check for knight attacks
     fn is_attacked_by_knights(&self, square: u8, side: bool) -> bool {
-        let knight_attacks = [
-            -17, -15, -10, -6, 6, 10, 15, 17
-        ];
-
-        for &offset in &knight_attacks {
-            let attack_square = (square as i8).wrapping_add(offset) as u8;
-            if self.is_on_board(attack_square) {
-                let knight_bitboard = if side == WHITE {
-                    self.black_knights
-                } else {
-                    self.white_knights
-                };
-                if knight_bitboard & (1u64 << attack_square) != 0 {
-                    // we can make sure rank or file doesnt chang eby more than 2 to avoid wrapping attacks
-                    let from_rank = get_rank(square);
-                    let to_rank = get_rank(attack_square);
-                    let from_file = get_file(square);
-                    let to_file = get_file(attack_square);
-                    if (from_rank as i8 - to_rank as i8).abs() <= 2 && (from_file as i8 - to_file as i8).abs() <= 2 {
-                        return true;
-                    }
-                }
-            }
-        }
-
-        false
+        let enemy_knights = if side == WHITE { self.black_knights } else { self.white_knights };
+        attacks::knight_attacks(square) & enemy_knights != 0
     }
 
     // Helper method to check for sliding piece attacks (bishops, rooks, queens)
-    fn is_attacked_by_sliding_pieces(&self, square: u8, side: bool, directions: &[i8]) -> bool {
-        // Check if a square is attacked by sliding pieces (bishops, rooks, queens)
-        //
-        // :param square: The square to check
-        // :param side: The side to check for attacks
-        // :param directions: The directions to check for attacks
-        // :return: True if the square is attacked, False otherwise
-
-        // TODO: Make sure no wrapping that shouldnt be allowed.
-
+    fn is_attacked_by_sliding_pieces(&self, square: u8, side: bool) -> bool {
         let (enemy_bishops, enemy_rooks, enemy_queens) = if side == WHITE {
             (self.black_bishops, self.black_rooks, self.black_queens)
         } else {
             (self.white_bishops, self.white_rooks, self.white_queens)
         };
 
-        for &dir in directions {
-            let mut attack_square = square as i8;
-
-            loop {
-                attack_square += dir;
-                if !self.is_on_board(attack_square as u8) {
-                    break;
-                }
-
-                let attack_square_bitboard = 1u64 << attack_square;
-                if (enemy_bishops | enemy_queens) & attack_square_bitboard != 0 && (dir == -9 || dir == -7 || dir == 7 || dir == 9) {
-                    // when moving like a bishop, need to make sure that rank and file dont change by different amounts
-                    let from_rank = get_rank(square);
-                    let to_rank = get_rank(attack_square as u8);
-                    let from_file = get_file(square);
-                    let to_file = get_file(attack_square as u8);
-                    if (from_rank as i8 - to_rank as i8).abs() == (from_file as i8 - to_file as i8).abs() {
-                        return true;
-                    }
-                }
-                if (enemy_rooks | enemy_queens) & attack_square_bitboard != 0 && (dir == -8 || dir == -1 || dir == 1 || dir == 8) {
-                    // when moving like a rook, need to make sure that it didnt wrap around the baord
-                    let from_rank = get_rank(square);
-                    let to_rank = get_rank(attack_square as u8);
-                    let from_file = get_file(square);
-                    let to_file = get_file(attack_square as u8);
-                    if (from_rank as i8 - to_rank as i8).abs() == 0 || (from_file as i8 - to_file as i8).abs() == 0 {
-                        return true;
-                    }
-                }
-                if self.is_occupied(attack_square as u8) {
-                    break;
-                }
-            }
-        }
-
-        false
+        let occupancy = self.occupancy();
+        attacks::bishop_attacks(square, occupancy) & (enemy_bishops | enemy_queens) != 0 ||
+            attacks::rook_attacks(square, occupancy) & (enemy_rooks | enemy_queens) != 0
     }
 
     // Helper method to check for king attacks
     fn is_attacked_by_king(&self, square: u8, side: bool) -> bool {
-        // check if distance between kings is > 9, if so, false
-        if (self.bitboard_to_square(self.black_king) as i8 - self.bitboard_to_square(self.white_king) as i8).abs() > 9 {
-            return false;
-        }
-        // check if kings are separated by more than one rank or more than one file
-
-
-        let king_attacks = [
-            -9, -8, -7, -1, 1, 7, 8, 9
-        ];
-
-        for &offset in &king_attacks {
-            let attack_square = (square as i8).wrapping_add(offset) as u8;
-            if self.is_on_board(attack_square) {
-                let king_bitboard = if side == WHITE {
-                    self.black_king
-                } else {
-                    self.white_king
-                };
-                if king_bitboard & (1u64 << attack_square) != 0 {
-                    // now make sure the king only moved at mmost one rank or file
-                    let from_rank = get_rank(square);
-                    let to_rank = get_rank(attack_square);
-                    let from_file = get_file(square);
-                    let to_file = get_file(attack_square);
-                    if (from_rank as i8 - to_rank as i8).abs() <= 1 && (from_file as i8 - to_file as i8).abs() <= 1 {
-                        return true;
-                    }
-                }
-            }
-        }
-
-        false
+        let enemy_king = if side == WHITE { self.black_king } else { self.white_king };
+        attacks::king_attacks(square) & enemy_king != 0
     }
 
     // Generate moves for each piece type
-    fn generate_pawn_moves(&self) -> Vec<Move> {
-        let mut moves = Vec::new();
+    fn generate_pawn_moves(&self, moves: &mut Vec<Move>) {
         let pawns = if self.side_to_move == WHITE { self.white_pawns } else { self.black_pawns };
         let start_rank = if self.side_to_move == WHITE { 1 } else { 6 };
         let direction = if self.side_to_move == WHITE { 8 } else { -8 };
+        let occupancy = self.occupancy();
+        let enemy_pieces = self.side_occupancy(!self.side_to_move);
 
-        for from in 0..64 {
-            let from_mask = 1u64 << from;
-            if pawns & from_mask != 0 {
-                // Single move forward
-                let to = (from as i8 + direction) as u8;
-                if !self.is_occupied(to) {
-                    // Check for promotion
-                    if get_rank(to) == if self.side_to_move == WHITE { 7 } else { 0 } {
-                        moves.push(Move { from, to, promotion: Some(PieceType::Queen), piece_type: PieceType::Pawn });
-                        moves.push(Move { from, to, promotion: Some(PieceType::Rook), piece_type: PieceType::Pawn });
-                        moves.push(Move { from, to, promotion: Some(PieceType::Bishop), piece_type: PieceType::Pawn });
-                        moves.push(Move { from, to, promotion: Some(PieceType::Knight), piece_type: PieceType::Pawn });
-                    } else {
-                        moves.push(Move { from, to, promotion: None, piece_type: PieceType::Pawn });
-                    }
-                }
+        let mut remaining = pawns;
+        while remaining != 0 {
+            let from = pop_lsb(&mut remaining);
+
+            // Single move forward
+            let to = (from as i8 + direction) as u8;
+            if occupancy & (1u64 << to) == 0 {
+                self.push_pawn_move(moves, from, to);
 
                 // Double move forward
-                if get_rank(from as u8) == start_rank {
+                if get_rank(from) == start_rank {
                     let to = (from as i8 + 2 * direction) as u8;
-                    if !self.is_occupied(to) && !self.is_occupied((from as i8 + direction) as u8) {
-                        moves.push(Move { from, to, promotion: None, piece_type: PieceType::Pawn });
+                    if occupancy & (1u64 << to) == 0 {
+                        moves.push(Move::new(from, to, PieceType::Pawn, None));
                     }
                 }
+            }
 
-                // Captures
-                let attack_offsets = if self.side_to_move == WHITE { [7, 9] } else { [-9, -7] };
-                for &offset in &attack_offsets {
-                    let to = (from as i8 + offset) as u8;
-                    if self.is_on_board(to) && self.is_occupied_by_opponent(to, self.side_to_move) {
-                        let from_file = from % 8;
-                        let to_file = to % 8;
-                        // Check if the move does not wrap around the board
-                        if (from_file as i8 - to_file as i8).abs() <= 1 {
-                            // Check for promotion
-                            if get_rank(to) == if self.side_to_move == WHITE { 7 } else { 0 } {
-                                moves.push(Move { from, to, promotion: Some(PieceType::Queen), piece_type: PieceType::Pawn });
-                                moves.push(Move { from, to, promotion: Some(PieceType::Rook), piece_type: PieceType::Pawn });
-                                moves.push(Move { from, to, promotion: Some(PieceType::Bishop), piece_type: PieceType::Pawn });
-                                moves.push(Move { from, to, promotion: Some(PieceType::Knight), piece_type: PieceType::Pawn });
-                            } else {
-                                moves.push(Move { from, to, promotion: None, piece_type: PieceType::Pawn });
-                            }
-                        }
-                    }
-                }
+            // Captures
+            let attacked = attacks::pawn_attacks(from, self.side_to_move);
+            let mut targets = attacked & enemy_pieces;
+            while targets != 0 {
+                let to = pop_lsb(&mut targets);
+                self.push_pawn_move(moves, from, to);
+            }
 
-                // En passant captures
-                if let Some(en_passant_square) = self.en_passant {
-                    for &offset in &attack_offsets {
-
-                        let to = (from as i8 + offset) as u8;
-                        if to == en_passant_square {
-                            // Check if the move does not wrap around the board
-                            let from_file = from % 8;
-                            let to_file = to % 8;
-                            if (from_file as i8 - to_file as i8).abs() <= 1 {
-                                let captured_pawn_square = if self.side_to_move == WHITE {
-                                    en_passant_square.wrapping_sub(8)
-                                } else {
-                                    en_passant_square.wrapping_add(8)
-                                };
-
-                                // Create a temporary copy of the board to simulate the move
-                                // TODO: remove board copy! and use unmake move
-                                let mut board_copy = self.clone();
-
-                                // Simulate the en passant capture
-                                board_copy.make_move(Move {
-                                    from,
-                                    to,
-                                    promotion: None,
-                                    piece_type: PieceType::Pawn,
-                                });
-
-                                // IMPORTANT: Here, you need to remove the pawn that was captured en passant
-                                let captured_pawn_bitboard = 1u64 << captured_pawn_square;
-                                if self.side_to_move == WHITE {
-                                    board_copy.black_pawns &= !captured_pawn_bitboard;
-                                } else {
-                                    board_copy.white_pawns &= !captured_pawn_bitboard;
-                                }
-
-                                // Check if this leaves the king in check
-                                if !board_copy.is_in_check(self.side_to_move) {
-                                    moves.push(Move {
-                                        from,
-                                        to,
-                                        promotion: None,
-                                        piece_type: PieceType::Pawn,
-                                    });
-                                }
-                            }
-                        }
-                    }
+            // En passant captures. Whether they expose the king is checked with the other moves in generate_legal_moves.
+            if let Some(en_passant_square) = self.en_passant {
+                if attacked & (1u64 << en_passant_square) != 0 {
+                    moves.push(Move::new(from, en_passant_square, PieceType::Pawn, None));
                 }
             }
         }
-
-        moves
     }
 
-    fn generate_knight_moves(&self) -> Vec<Move> {
-        let mut moves = Vec::new();
-        let knights = if self.side_to_move == WHITE { self.white_knights } else { self.black_knights };
-
-        for from in 0..64 {
-            let from_mask = 1u64 << from;
-            if knights & from_mask != 0 {
-                // The possible moves for a knight from any position
-                let knight_moves = [
-                    -17, -15, -10, -6, 6, 10, 15, 17
-                ];
-                for &move_offset in &knight_moves {
-                    let to = (from as i8).wrapping_add(move_offset) as u8;
-                
-                    // Check if the move is within the bounds of the board
-                    if self.is_on_board(to) && !self.is_occupied_by_side(to, self.side_to_move) {
-                        let from_row = from / 8;
-                        let from_col = from % 8;
-                        let to_row = to / 8;
-                        let to_col = to % 8;
-                
-                        // Check if the move is a valid knight move
-                        if (from_row as i8 - to_row as i8).abs() == 2 && (from_col as i8 - to_col as i8).abs() == 1 ||
-                           (from_row as i8 - to_row as i8).abs() == 1 && (from_col as i8 - to_col as i8).abs() == 2 {
-                            moves.push(Move {
-                                from,
-                                to,
-                                promotion: None,
-                                piece_type: PieceType::Knight,
-                            });
-                        }
-                    }
-                }
-            }
+    // Adds a pawn move, expanded into the four promotions when it reaches the last rank
+    fn push_pawn_move(&self, moves: &mut Vec<Move>, from: u8, to: u8) {
+        if get_rank(to) == if self.side_to_move == WHITE { 7 } else { 0 } {
+            moves.push(Move { from, to, promotion: Some(PieceType::Queen), piece_type: PieceType::Pawn });
+            moves.push(Move { from, to, promotion: Some(PieceType::Rook), piece_type: PieceType::Pawn });
+            moves.push(Move { from, to, promotion: Some(PieceType::Bishop), piece_type: PieceType::Pawn });
+            moves.push(Move { from, to, promotion: Some(PieceType::Knight), piece_type: PieceType::Pawn });
+        } else {
+            moves.push(Move { from, to, promotion: None, piece_type: PieceType::Pawn });
         }
+    }
 
-        moves
+    fn generate_knight_moves(&self, moves: &mut Vec<Move>) {
+        let knights = if self.side_to_move == WHITE { self.white_knights } else { self.black_knights };
+        self.generate_piece_moves(moves, knights, PieceType::Knight, |from, _| attacks::knight_attacks(from));
     }
-    fn generate_bishop_moves(&self) -> Vec<Move> {
-        let mut moves = Vec::new();
-        let bishops = if self.side_to_move == WHITE { self.white_bishops } else { self.black_bishops };
-    
-        for from in 0..64 {
-            let from_mask = 1u64 << from;
-            if bishops & from_mask != 0 {
-                // The possible directions for a bishop from any position
-                let directions = [-9, -7, 7, 9];
-    
-                for &dir in &directions {
-                    let mut to = from as i8;
-    
-                    loop {
-                        to += dir; // Move in the direction
-    
-                        // Additional check to prevent wrapping around
-                        if (dir == -9 && (to % 8 == 7 || to < 0)) || (dir == -7 && (to % 8 == 0 || to < 0)) ||
-                           (dir == 9 && (to % 8 == 0 || to > 63)) || (dir == 7 && (to % 8 == 7 || to > 63)) {
-                            break;
-                        }
-    
-                        // Check if the move is within the bounds of the board
-                        if !self.is_on_board(to as u8) {
-                            break;
-                        }
-        
-                        if self.is_occupied_by_side(to as u8, self.side_to_move) {
-                            break; // Stop if we hit a friendly piece
-                        }
-        
-                        moves.push(Move {
-                            from,
-                            to: to as u8,
-                            promotion: None,
-                            piece_type: PieceType::Bishop,
-                        });
-        
-                        if self.is_occupied_by_opponent(to as u8, self.side_to_move) {
-                            break; // Stop if we hit an opponent's piece (capture it)
-                        }
-                    }
-                }
-            }
-        }
 
-        moves
+    fn generate_bishop_moves(&self, moves: &mut Vec<Move>) {
+        let bishops = if self.side_to_move == WHITE { self.white_bishops } else { self.black_bishops };
+        self.generate_piece_moves(moves, bishops, PieceType::Bishop, attacks::bishop_attacks);
     }
 
-    fn generate_rook_moves(&self) -> Vec<Move> {
-        let mut moves = Vec::new();
+    fn generate_rook_moves(&self, moves: &mut Vec<Move>) {
         let rooks = if self.side_to_move == WHITE { self.white_rooks } else { self.black_rooks };
-    
-        for from in 0..64 {
-            let from_mask = 1u64 << from;
-            if rooks & from_mask != 0 {
-                // The possible directions for a rook from any position
-                let directions = [-8, -1, 1, 8];
-    
-                for &dir in &directions {
-                    let mut to = from as i8;
-    
-                    loop {
-                        to += dir; // Move in the direction
-    
-                        // Additional check to prevent wrapping around
-                        if (dir == -1 && to % 8 == 7) || (dir == 1 && to % 8 == 0) {
-                            break;
-                        }
-    
-                        if !self.is_on_board(to as u8) || self.is_occupied_by_side(to as u8, self.side_to_move) {
-                            break; // Stop if we hit the edge of the board or a friendly piece
-                        }
-    
-                        moves.push(Move {
-                            from,
-                            to: to as u8,
-                            promotion: None,
-                            piece_type: PieceType::Rook,
-                        });
-    
-                        if self.is_occupied_by_opponent(to as u8, self.side_to_move) {
-                            break; // Stop if we hit an opponent's piece (capture it)
-                        }
-                    }
-                }
-            }
-        }
-    
-        moves
+        self.generate_piece_moves(moves, rooks, PieceType::Rook, attacks::rook_attacks);
     }
-    
 
-    fn generate_queen_moves(&self) -> Vec<Move> {
-        let mut moves = Vec::new();
+    fn generate_queen_moves(&self, moves: &mut Vec<Move>) {
         let queens = if self.side_to_move == WHITE { self.white_queens } else { self.black_queens };
-    
-        for from in 0..64 {
-            let from_mask = 1u64 << from;
-            if queens & from_mask != 0 {
-                // The possible directions for a queen from any position
-                let directions = [-9, -8, -7, -1, 1, 7, 8, 9];
-    
-                for &dir in &directions {
-                    let mut to = from as i8;
-    
-                    loop {
-                        to += dir; // Move in the direction
-    
-                        // Additional check to prevent wrapping around
-                        if (dir == -1 && to % 8 == 7) || (dir == 1 && to % 8 == 0) ||
-                           (dir == -9 && (to % 8 == 7 || to < 0)) || (dir == -7 && (to % 8 == 0 || to < 0)) ||
-                           (dir == 9 && (to % 8 == 0 || to > 63)) || (dir == 7 && (to % 8 == 7 || to > 63)) {
-                            break;
-                        }
-    
-                        if !self.is_on_board(to as u8) || self.is_occupied_by_side(to as u8, self.side_to_move) {
-                            break; // Stop if we hit the edge of the board or a friendly piece
-                        }
-    
-                        moves.push(Move {
-                            from,
-                            to: to as u8,
-                            promotion: None,
-                            piece_type: PieceType::Queen,
-                        });
-    
-                        if self.is_occupied_by_opponent(to as u8, self.side_to_move) {
-                            break; // Stop if we hit an opponent's piece (capture it)
-                        }
-                    }
-                }
-            }
-        }
-    
-        moves
+        self.generate_piece_moves(moves, queens, PieceType::Queen, attacks::queen_attacks);
     }
-    
 
-    fn generate_king_moves(&self) -> Vec<Move> {
-        let mut moves = Vec::new();
+    fn generate_king_moves(&self, moves: &mut Vec<Move>) {
         let king = if self.side_to_move == WHITE { self.white_king } else { self.black_king };
-    
-        for from in 0..64 {
-            let from_mask = 1u64 << from;
-            if king & from_mask != 0 {
-                let king_moves = [-9, -8, -7, -1, 1, 7, 8, 9];
-                let from_file = from % 8;
-    
-                for &move_offset in &king_moves {
-                    let to = (from as i8 + move_offset) as u8;
-    
-                    // Additional check to prevent wrapping around
-                    let to_file = to % 8;
-                    if (from_file as i8 - to_file as i8).abs() > 1 {
-                        continue; // Skip this move as it would wrap around the board
-                    }
-    
-                    // Check if the move is within the bounds of the board and not landing on a friendly piece
-                    if self.is_on_board(to) && !self.is_occupied_by_side(to, self.side_to_move) {
-                        moves.push(Move {
-                            from,
-                            to,
-                            promotion: None,
-                            piece_type: PieceType::King,
-                        });
-                    }
-                }
-                
-                if (self.side_to_move == WHITE && self.castling_rights & 0b0001 != 0) ||
-                (self.side_to_move == BLACK && self.castling_rights & 0b0100 != 0) {
-                    if self.can_castle_kingside() {
-                        let castle_move_to = if self.side_to_move == WHITE { 6 } else { 62 };
-                        moves.push(Move::new(from, castle_move_to, PieceType::King, None));
-                    }
-                }
-                if (self.side_to_move == WHITE && self.castling_rights & 0b0010 != 0) ||
-                    (self.side_to_move == BLACK && self.castling_rights & 0b1000 != 0) {
-                        if self.can_castle_queenside() {
-                            let castle_move_to = if self.side_to_move == WHITE { 2 } else { 58 };
-                            moves.push(Move::new(from, castle_move_to, PieceType::King, None));
-                        }
-                }
+        self.generate_piece_moves(moves, king, PieceType::King, |from, _| attacks::king_attacks(from));
+
+        if king != 0 {
+            let from = self.bitboard_to_square(king);
+            if ((self.side_to_move == WHITE && self.castling_rights & 0b0001 != 0) ||
+                (self.side_to_move == BLACK && self.castling_rights & 0b0100 != 0)) &&
+                self.can_castle_kingside() {
+                let castle_move_to = if self.side_to_move == WHITE { 6 } else { 62 };
+                moves.push(Move::new(from, castle_move_to, PieceType::King, None));
+            }
+            if ((self.side_to_move == WHITE && self.castling_rights & 0b0010 != 0) ||
+                (self.side_to_move == BLACK && self.castling_rights & 0b1000 != 0)) &&
+                self.can_castle_queenside() {
+                let castle_move_to = if self.side_to_move == WHITE { 2 } else { 58 };
+                moves.push(Move::new(from, castle_move_to, PieceType::King, None));
             }
         }
+    }
 
-        moves
+    // Generates the moves of every piece in `pieces`, given a function returning the squares
+    // a piece on `from` attacks for some board occupancy
+    fn generate_piece_moves(&self, moves: &mut Vec<Move>, pieces: u64, piece_type: PieceType, attacks_from: impl Fn(u8, u64) -> u64) {
+        let occupancy = self.occupancy();
+        let own_pieces = self.side_occupancy(self.side_to_move);
+
+        let mut remaining = pieces;
+        while remaining != 0 {
+            let from = pop_lsb(&mut remaining);
+            let mut targets = attacks_from(from, occupancy) & !own_pieces;
+            while targets != 0 {
+                let to = pop_lsb(&mut targets);
+                moves.push(Move::new(from, to, piece_type, None));
+            }
+        }
     }
 
     // Helper method to determine if kingside castling is legal
@@ -944,34 +598,38 @@ impl Board {
         true
     }
 
-    // Check if a square is occupied
-    fn is_occupied(&self, square: u8) -> bool {
-        let mask = 1u64 << square;
-
-        let is_occupied_bool = (self.white_pawns | self.white_knights | self.white_bishops |
-         self.white_rooks | self.white_queens | self.white_king |
-         self.black_pawns | self.black_knights | self.black_bishops |
-         self.black_rooks | self.black_queens | self.black_king) & mask != 0;
-
-         is_occupied_bool
-    }
-
-    // Check if a square is on the board
-    fn is_on_board(&self, square: u8) -> bool {
-        square < 64
+    // Bitboard of every piece on the board
+    pub fn occupancy(&self) -> u64 {
+        self.side_occupancy(WHITE) | self.side_occupancy(BLACK)
     }
 
-    // Check if a square is occupied by a piece of the given side
-    fn is_occupied_by_side(&self, square: u8, side: bool) -> bool {
-        let mask = 1u64 << square;
-        let occupied = if side == WHITE {
+    // Bitboard of the pieces of one side
+    pub fn side_occupancy(&self, side: bool) -> u64 {
+        if side == WHITE {
             self.white_pawns | self.white_knights | self.white_bishops |
             self.white_rooks | self.white_queens | self.white_king
         } else {
             self.black_pawns | self.black_knights | self.black_bishops |
             self.black_rooks | self.black_queens | self.black_king
-        };
-        occupied & mask != 0
+        }
+    }
+
+    // Bitboard of one kind of piece of one side
+    pub fn pieces(&self, piece_type: PieceType, side: bool) -> u64 {
+        match (piece_type, side) {
+            (PieceType::Pawn, WHITE) => self.white_pawns,
+            (PieceType::Knight, WHITE) => self.white_knights,
+            (PieceType::Bishop, WHITE) => self.white_bishops,
+            (PieceType::Rook, WHITE) => self.white_rooks,
+            (PieceType::Queen, WHITE) => self.white_queens,
+            (PieceType::King, WHITE) => self.white_king,
+            (PieceType::Pawn, BLACK) => self.black_pawns,
+            (PieceType::Knight, BLACK) => self.black_knights,
+            (PieceType::Bishop, BLACK) => self.black_bishops,
+            (PieceType::Rook, BLACK) => self.black_rooks,
+            (PieceType::Queen, BLACK) => self.black_queens,
+            (PieceType::King, BLACK) => self.black_king,
+        }
     }
 
     // // Call this function after a move is made to update the checkmate and draw status
@@ -1000,19 +658,19 @@ impl Board {
     // Determine if the current position is a draw
     pub fn check_for_draw(&mut self) -> bool {
         // Check for insufficient material
-        // if self.is_insufficient_material() {
-        //     return true;
-        // }
+        if self.is_insufficient_material() {
+            return true;
+        }
 
         // Check for stalemate
         if !self.is_in_check(self.side_to_move) && self.generate_legal_moves().is_empty() {
             return true;
         }
 
-        // // Check for threefold repetition
-        // if self.is_threefold_repetition() {
-        //     return true;
-        // }
+        // Check for threefold repetition
+        if self.is_threefold_repetition() {
+            return true;
+        }
 
         // Check for fifty-move rule
         if self.halfmove_clock >= 100 {
@@ -1022,6 +680,61 @@ impl Board {
         false
     }
 
+    // Neither side can ever checkmate: bare kings, a single minor piece, or only bishops that
+    // all stand on squares of the same colour
+    pub fn is_insufficient_material(&self) -> bool {
+        let heavy_pieces_and_pawns = self.white_pawns | self.black_pawns | self.white_rooks | self.black_rooks | self.white_queens | self.black_queens;
+        if heavy_pieces_and_pawns != 0 {
+            return false;
+        }
+
+        let knights = self.white_knights | self.black_knights;
+        let bishops = self.white_bishops | self.black_bishops;
+        if (knights | bishops).count_ones() <= 1 {
+            return true;
+        }
+
+        knights == 0 && (bishops & LIGHT_SQUARES == 0 || bishops & !LIGHT_SQUARES == 0)
+    }
+
+    // The side has a piece other than pawns and its king. Without one, zugzwang is common enough
+    // that passing the move is no longer a safe guess of what the side can do at least.
+    pub fn has_non_pawn_material(&self, side: bool) -> bool {
+        self.pieces(PieceType::Knight, side) | self.pieces(PieceType::Bishop, side) | self.pieces(PieceType::Rook, side) | self.pieces(PieceType::Queen, side) != 0
+    }
+
+    // A pawn of `side` stands next to the pawn that just moved past `square`, ready to take it en
+    // passant. Only then is the en passant square part of the position: without a pawn to use it,
+    // the position is the same one as when it comes up again later.
+    pub fn can_capture_en_passant(&self, square: u8, side: bool) -> bool {
+        attacks::pawn_attacks(square, !side) & self.pieces(PieceType::Pawn, side) != 0
+    }
+
+    // How many times the current position occurred before. Only positions since the last capture or
+    // pawn move can repeat, and only those with the same side to move, so every other key is checked
+    // going back halfmove_clock plies.
+    pub fn repetition_count(&self) -> usize {
+        let lookback = (self.halfmove_clock as usize).min(self.history.len());
+        self.history[self.history.len() - lookback..]
+            .iter()
+            .rev()
+            .skip(1)
+            .step_by(2)
+            .filter(|&&hash| hash == self.hash)
+            .count()
+    }
+
+    // The position occurred at least once before. Inside a search this is scored as a draw,
+    // since whoever could avoid the repetition had the chance to and didn't.
+    pub fn is_repetition(&self) -> bool {
+        self.repetition_count() >= 1
+    }
+
+    // The position is on the board for the third time
+    pub fn is_threefold_repetition(&self) -> bool {
+        self.repetition_count() >= 2
+    }
+
     // Method to print the board
     pub fn print_board(&self) {
         println!("  a b c d e f g h");
@@ -1067,32 +780,46 @@ impl Board {
         '.'
     }
 
+    // Returns the piece type and side of the piece on a square, if any
+    pub fn piece_at(&self, square: u8) -> Option<(PieceType, bool)> {
+        let mask = 1u64 << square;
+        if (self.white_pawns | self.white_knights | self.white_bishops |
+            self.white_rooks | self.white_queens | self.white_king) & mask != 0 {
+            Some((self.get_piece_type(square), WHITE))
+        } else if (self.black_pawns | self.black_knights | self.black_bishops |
+            self.black_rooks | self.black_queens | self.black_king) & mask != 0 {
+            Some((self.get_piece_type(square), BLACK))
+        } else {
+            None
+        }
+    }
+
     pub fn get_piece_type(&self, square: u8) -> PieceType {
         let mask = 1u64 << square;
         if self.white_pawns & mask != 0 {
-            return PieceType::Pawn;
+            PieceType::Pawn
         } else if self.white_knights & mask != 0 {
-            return PieceType::Knight;
+            PieceType::Knight
         } else if self.white_bishops & mask != 0 {
-            return PieceType::Bishop;
+            PieceType::Bishop
         } else if self.white_rooks & mask != 0 {
-            return PieceType::Rook;
+            PieceType::Rook
         } else if self.white_queens & mask != 0 {
-            return PieceType::Queen;
+            PieceType::Queen
         } else if self.white_king & mask != 0 {
-            return PieceType::King;
+            PieceType::King
         } else if self.black_pawns & mask != 0 {
-            return PieceType::Pawn;
+            PieceType::Pawn
         } else if self.black_knights & mask != 0 {
-            return PieceType::Knight;
+            PieceType::Knight
         } else if self.black_bishops & mask != 0 {
-            return PieceType::Bishop;
+            PieceType::Bishop
         } else if self.black_rooks & mask != 0 {
-            return PieceType::Rook;
+            PieceType::Rook
         } else if self.black_queens & mask != 0 {
-            return PieceType::Queen;
+            PieceType::Queen
         } else if self.black_king & mask != 0 {
-            return PieceType::King;
+            PieceType::King
         } else {
             panic!("No piece on square {}", square);
         }
@@ -1136,3 +863,134 @@ impl Board {
 
 //     // ... more tests ...
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{GameResult, Termination};
+
+    // Plays SAN moves on the board
+    fn play(board: &mut Board, sans: &str) {
+        for san in sans.split_whitespace() {
+            let mv = board.parse_san(san).unwrap();
+            board.make_move(mv);
+        }
+    }
+
+    #[test]
+    fn repetition_counts_earlier_occurrences() {
+        let mut board = Board::new();
+        play(&mut board, "Nf3 Nf6 Ng1");
+        assert_eq!(board.repetition_count(), 0);
+
+        play(&mut board, "Ng8");
+        assert_eq!(board.repetition_count(), 1);
+        assert!(board.is_repetition() && !board.is_threefold_repetition());
+
+        play(&mut board, "Nf3 Nf6 Ng1 Ng8");
+        assert_eq!(board.repetition_count(), 2);
+        assert!(board.is_threefold_repetition());
+    }
+
+    #[test]
+    fn repetition_through_transpositions() {
+        // The start position comes back three times, along different move orders
+        let mut board = Board::new();
+        play(&mut board, "Nf3 Nf6 Nc3 Nc6 Nb1 Nb8 Ng1 Ng8");
+        assert_eq!(board.repetition_count(), 1);
+        play(&mut board, "Nc3 Nc6 Nf3 Nf6 Ng1 Nb8 Nb1 Ng8");
+        assert!(board.is_threefold_repetition());
+
+        // Positions in between repeat too, without the same moves leading to them. Nothing can take
+        // on e6, so the position right after e5 is the first of the three.
+        let mut board = Board::new();
+        play(&mut board, "e4 e5 Nf3 Nc6 Ng1 Nb8 Nc3 Nf6 Nb1 Ng8");
+        assert_eq!(board.repetition_count(), 2);
+    }
+
+    #[test]
+    fn lost_castling_rights_make_a_new_position() {
+        let mut board = Board::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        play(&mut board, "Rb1 Rb8 Ra1 Ra8");
+        assert!(!board.is_repetition());
+
+        play(&mut board, "Rb1 Rb8 Ra1 Ra8");
+        assert_eq!(board.repetition_count(), 1);
+        play(&mut board, "Rb1 Rb8 Ra1 Ra8");
+        assert!(board.is_threefold_repetition());
+    }
+
+    #[test]
+    fn en_passant_right_makes_a_new_position() {
+        // After e4 the d4 pawn may take en passant, the next time the pieces stand there it may not
+        let mut board = Board::from_fen("4k3/8/8/8/3p4/8/4P3/4K3 w - - 0 1").unwrap();
+        play(&mut board, "e4");
+        assert_eq!(board.en_passant, Some(20));
+        play(&mut board, "Kd7 Kd1 Ke8 Ke1");
+        assert!(!board.is_repetition());
+
+        play(&mut board, "Kd7 Kd1 Ke8 Ke1");
+        assert_eq!(board.repetition_count(), 1);
+    }
+
+    #[test]
+    fn double_push_nothing_can_take_repeats() {
+        // No black pawn can take on e3, so the position after e4 comes back twice more
+        let mut board = Board::from_fen("4k3/8/8/8/8/8/4P3/4K1N1 w - - 0 1").unwrap();
+        play(&mut board, "e4");
+        assert_eq!(board.en_passant, None);
+        play(&mut board, "Kd7 Nf3 Ke8 Ng1 Kd7 Nf3 Ke8 Ng1");
+        assert_eq!(board.repetition_count(), 2);
+        assert!(board.is_threefold_repetition());
+    }
+
+    #[test]
+    fn irreversible_moves_end_the_lookback() {
+        let mut board = Board::new();
+        play(&mut board, "Nf3 Nf6 Ng1 Ng8 e4");
+        play(&mut board, "Nf6 Nf3 Ng8 Ng1");
+        assert_eq!(board.halfmove_clock, 4);
+        assert_eq!(board.repetition_count(), 1);
+    }
+
+    fn insufficient(fen: &str) -> bool {
+        Board::from_fen(fen).unwrap().is_insufficient_material()
+    }
+
+    #[test]
+    fn bare_kings_and_single_minor_pieces_cannot_mate() {
+        assert!(insufficient("4k3/8/8/8/8/8/8/4K3 w - - 0 1"));
+        assert!(insufficient("4k3/8/8/8/8/8/8/2B1K3 w - - 0 1"));
+        assert!(insufficient("4k3/8/8/8/8/8/8/1N2K3 w - - 0 1"));
+        assert!(insufficient("1n2k3/8/8/8/8/8/8/4K3 b - - 0 1"));
+    }
+
+    #[test]
+    fn bishops_on_one_colour_cannot_mate() {
+        // c1 and f8 are both dark squares, as are a3 and h6
+        assert!(insufficient("5b2/4k3/8/8/8/8/8/2B1K3 w - - 0 1"));
+        assert!(insufficient("4k3/8/7B/8/8/B7/8/2B1K3 w - - 0 1"));
+
+        // c8 is a light square
+        assert!(!insufficient("2b1k3/8/8/8/8/8/8/2B1K3 w - - 0 1"));
+    }
+
+    #[test]
+    fn enough_material_to_mate() {
+        assert!(!insufficient("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1"));
+        assert!(!insufficient("4k3/8/8/8/8/8/8/R3K3 w - - 0 1"));
+        assert!(!insufficient("4k3/8/8/8/8/8/8/3QK3 w - - 0 1"));
+        assert!(!insufficient("4k3/8/8/8/8/8/8/1NB1K3 w - - 0 1"));
+        assert!(!insufficient("4k3/8/8/8/8/8/8/1N2K1N1 w - - 0 1"));
+        assert!(!insufficient("1n2k3/8/8/8/8/8/8/2B1K3 w - - 0 1"));
+    }
+
+    #[test]
+    fn insufficient_material_ends_the_game() {
+        let mut board = Board::from_fen("4k3/8/8/8/8/8/3r4/4K3 w - - 0 1").unwrap();
+        assert_eq!(board.game_result(), None);
+
+        play(&mut board, "Kxd2");
+        assert_eq!(board.game_result(), Some(GameResult::Draw(Termination::InsufficientMaterial)));
+    }
+}
@@ -1,88 +1,372 @@
 // engine.rs
-//TODO: Make sure alpha beta pruning is set up corerctly
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use std::collections::HashMap;
-
-use crate::board::Board;
-use crate::r#move::ScoredMove;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::thread;
+
+use crate::board::{Board, UndoState};
+use crate::evaluator::{ClassicalEvaluator, Evaluator};
+use crate::movepick::{mvv_lva, History, Killers, MovePicker};
+use crate::r#move::{Move, ScoredMove};
 use crate::piece::PieceType;
+use crate::score::{Score, MAX_PLY};
+use crate::search::SearchHandle;
+use crate::time::TimeManager;
+use crate::tt::{Bound, TranspositionTable};
 use crate::utils::{get_rank, get_file};
 
-use rayon::prelude::*;
-use rayon::ThreadPoolBuilder;
+// Deepest iteration the iterative deepening loop will start
+pub const MAX_DEPTH: usize = 64;
+
+// Most threads a single search may use
+pub const MAX_THREADS: usize = 256;
+
+// Which iterations each helper thread skips in a parallel search, so the helpers spread over
+// several depths instead of searching the same tree in lockstep. Helper i skips depth d when
+// (d + HELPER_SKIP_PHASE[i]) / HELPER_SKIP_SIZE[i] is odd; the tables repeat after 20 helpers.
+const HELPER_SKIP_SIZE: [usize; 20] = [1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 3, 3, 4, 4, 4, 4, 4, 4, 4, 4];
+const HELPER_SKIP_PHASE: [usize; 20] = [0, 1, 0, 1, 2, 3, 0, 1, 2, 3, 4, 5, 0, 1, 2, 3, 4, 5, 6, 7];
+
+// How many nodes to search between clock checks
+const TIME_CHECK_INTERVAL: u64 = 1024;
+
+// Safety margin for delta pruning in quiescence search, in centipawns
+const DELTA_MARGIN: i32 = 200;
+
+// Null-move pruning is tried from this depth on, searching the pass this much shallower
+const NULL_MOVE_MIN_DEPTH: usize = 3;
+const NULL_MOVE_REDUCTION: usize = 2;
+
+// Late move reductions start with this move at this depth
+const LMR_MIN_MOVE: usize = 3;
+const LMR_MIN_DEPTH: usize = 3;
+
+// Reverse futility pruning is tried up to this depth, with this margin per ply of depth
+const REVERSE_FUTILITY_MAX_DEPTH: usize = 6;
+const REVERSE_FUTILITY_MARGIN: i32 = 120;
+
+// Futility pruning is tried up to this depth, with this margin per ply of depth
+const FUTILITY_MAX_DEPTH: usize = 3;
+const FUTILITY_MARGIN: i32 = 150;
+
+// How many plies shallower a late quiet move is searched. The reduction grows with both the depth
+// and how far down the move ordering the move is, and always leaves at least quiescence to search.
+fn late_move_reduction(depth: usize, move_index: usize) -> usize {
+    let reduction = 0.75 + (depth as f64).ln() * (move_index as f64).ln() / 2.25;
+    (reduction as usize).clamp(1, depth - 1)
+}
+
+// Limits for a single search, as given by a UCI "go" command. Times are in milliseconds.
+#[derive(Clone, Debug, Default)]
+pub struct SearchLimits {
+    pub depth: Option<usize>,
+    pub nodes: Option<u64>,
+    pub movetime: Option<u64>,
+    pub wtime: Option<u64>,
+    pub btime: Option<u64>,
+    pub winc: Option<u64>,
+    pub binc: Option<u64>,
+    pub movestogo: Option<u64>,
+    pub infinite: bool,
+}
+
+impl SearchLimits {
+    // Search to a fixed depth, with no time or node limit
+    pub fn depth(depth: usize) -> Self {
+        Self { depth: Some(depth), ..Self::default() }
+    }
+}
+
+// The selective parts of the search, each of which can be switched off to measure what it is worth.
+// Everything is on by default.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SearchConfig {
+    pub null_move: bool,               // Pass the move; if the opponent still can't reach beta, prune
+    pub late_move_reductions: bool,    // Search quiet moves late in the ordering shallower
+    pub reverse_futility: bool,        // Prune when the static evaluation is far above beta near the horizon
+    pub futility: bool,                // Skip quiet moves that can't bring a hopeless position up to alpha
+    pub check_extensions: bool,        // Search a ply deeper when in check
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        Self {
+            null_move: true,
+            late_move_reductions: true,
+            reverse_futility: true,
+            futility: true,
+            check_extensions: true,
+        }
+    }
+}
+
+impl SearchConfig {
+    // Plain alpha-beta, every move searched to full depth
+    pub fn full_width() -> Self {
+        Self {
+            null_move: false,
+            late_move_reductions: false,
+            reverse_futility: false,
+            futility: false,
+            check_extensions: false,
+        }
+    }
+}
+
+// Progress report for one completed iteration of the search
+#[derive(Clone, Debug)]
+pub struct SearchInfo {
+    pub depth: usize,
+    pub seldepth: usize, // Deepest ply reached, quiescence included
+    pub score: Score,
+    pub nodes: u64,
+    pub nps: u64,
+    pub time: Duration,
+    pub hashfull: usize,
+    pub pv: Vec<Move>,
+}
 
 #[derive(Clone, Debug)]
 pub struct Engine {
     board: Board,
-    // Engine-specific fields, if any
+    stop: Arc<AtomicBool>,      // Set to abort the search, shared by every thread searching this position
+    nodes: Arc<AtomicU64>,      // Nodes searched so far, shared by every thread searching this position
+    seldepth: Arc<AtomicUsize>, // Deepest ply reached so far, shared the same way
+    node_limit: Option<u64>,
+    deadline: Option<Instant>,  // Hard time limit, the search is aborted when it passes
+    time_manager: TimeManager,
+    // Shared by every thread searching this position, and kept between searches. Allocated by the
+    // first search, so an engine that is only used for perft or evaluation doesn't pay for it.
+    tt: Option<Arc<TranspositionTable>>,
+    killers: Killers,
+    history: History,
+    evaluator: Box<dyn Evaluator>, // Each thread searches with its own copy
+    threads: usize, // Threads searching in parallel, 1 searches deterministically on the calling thread
+    config: SearchConfig,
+    null_move_ply: Option<usize>, // Ply of the position reached by a null move, where another one would undo it
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Engine {
     pub fn new() -> Self {
         // Initialize the engine
-        Self {
-            board: Board::new(),
-        }
+        Self::with_board(None)
     }
 
     pub fn with_board(board: Option<Board>) -> Self {
-        match board {
-            Some(existing_board) => Self { board: existing_board },
-            None => Self::new(),
+        Self {
+            board: board.unwrap_or_default(),
+            stop: Arc::new(AtomicBool::new(false)),
+            nodes: Arc::new(AtomicU64::new(0)),
+            seldepth: Arc::new(AtomicUsize::new(0)),
+            node_limit: None,
+            deadline: None,
+            time_manager: TimeManager::unlimited(),
+            tt: None,
+            killers: Killers::default(),
+            history: History::default(),
+            evaluator: Box::new(ClassicalEvaluator::new()),
+            threads: 1,
+            config: SearchConfig::default(),
+            null_move_ply: None,
         }
     }
 
-    // A very simple evaluation function
-    pub fn evaluate(&mut self) -> i32 {
+    // Use a transposition table that outlives this engine, e.g. one kept across the moves of a game
+    pub fn set_transposition_table(&mut self, tt: Arc<TranspositionTable>) {
+        self.tt = Some(tt);
+    }
+
+    // The table the engine searches with, allocated with the default size if it has none yet
+    pub fn transposition_table(&mut self) -> Arc<TranspositionTable> {
+        Arc::clone(self.tt.get_or_insert_with(|| Arc::new(TranspositionTable::default())))
+    }
+
+    fn tt(&self) -> &TranspositionTable {
+        self.tt.as_deref().expect("the transposition table is allocated when a search begins")
+    }
+
+    pub fn set_threads(&mut self, threads: usize) {
+        self.threads = threads.clamp(1, MAX_THREADS);
+    }
+
+    // Replaces the evaluation the search uses, the classical one by default
+    pub fn set_evaluator(&mut self, evaluator: Box<dyn Evaluator>) {
+        self.evaluator = evaluator;
+    }
+
+    pub fn set_search_config(&mut self, config: SearchConfig) {
+        self.config = config;
+    }
+
+    pub fn search_config(&self) -> SearchConfig {
+        self.config
+    }
+
+    // Handle that can be used to stop a running search from another thread
+    pub fn stop_flag(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.stop)
+    }
+
+    // Evaluation in centipawns from the point of view of the side to move
+    pub fn evaluate(&mut self) -> Score {
         // Check for terminal conditions first
         if self.board.check_for_checkmate() {
-            return if self.board.side_to_move == true {
-                i32::MIN // Checkmate against White
-            } else {
-                i32::MAX // Checkmate against Black
-            };
+            return Score::mated_in(0); // The side to move is checkmated
         } else if self.board.check_for_draw() {
-            return 0; // Draw
+            return Score::DRAW;
+        }
+
+        self.static_eval()
+    }
+
+    // Static evaluation in centipawns from the point of view of the side to move, ignoring checkmate and draws
+    fn static_eval(&mut self) -> Score {
+        Score::cp(self.evaluator.evaluate(&self.board))
+    }
+
+    // Searches the `n_moves` best moves to a fixed depth with iterative deepening, passing the
+    // best moves found so far to `on_depth` after every iteration
+    pub fn search_moves<F: FnMut(usize, &[ScoredMove])>(&mut self, n_moves: usize, max_depth: usize, on_depth: F) -> Vec<ScoredMove> {
+        self.begin_search(&SearchLimits::depth(max_depth));
+        let best_moves = self.parallel_search(n_moves, max_depth, on_depth);
+        self.end_search();
+        best_moves
+    }
+
+    // Search within the given limits, reporting each completed iteration to `on_info`.
+    // Returns None only if the side to move has no legal moves.
+    pub fn search<F: FnMut(&SearchInfo)>(&mut self, limits: &SearchLimits, mut on_info: F) -> Option<ScoredMove> {
+        let start_time = Instant::now();
+        self.begin_search(limits);
+
+        let max_depth = limits.depth.unwrap_or(MAX_DEPTH).clamp(1, MAX_DEPTH);
+        let nodes = Arc::clone(&self.nodes);
+        let seldepth = Arc::clone(&self.seldepth);
+        let tt = self.transposition_table();
+        let best_moves = self.parallel_search(1, max_depth, |depth, best_moves| {
+            let nodes = nodes.load(Ordering::Relaxed);
+            let time = start_time.elapsed();
+            on_info(&SearchInfo {
+                depth,
+                seldepth: seldepth.load(Ordering::Relaxed),
+                score: best_moves[0].score,
+                nodes,
+                nps: nodes * 1000 / (time.as_millis() as u64).max(1),
+                time,
+                hashfull: tt.hashfull(),
+                pv: best_moves[0].pv.clone(),
+            });
+        });
+
+        self.end_search();
+        best_moves.into_iter().next()
+    }
+
+    // Reset the shared counters and arm the node and time limits. The stop flag is left alone,
+    // so a stop that arrives before the search gets going is not lost.
+    fn begin_search(&mut self, limits: &SearchLimits) {
+        self.nodes.store(0, Ordering::Relaxed);
+        self.seldepth.store(0, Ordering::Relaxed);
+        self.node_limit = limits.nodes;
+        self.transposition_table().new_search();
+        self.killers.clear();
+        self.history.clear();
+        self.null_move_ply = None;
+        self.time_manager = TimeManager::new(limits, self.board.side_to_move);
+        self.deadline = self.time_manager.hard_deadline();
+    }
+
+    // Clears the stop flag, which a stop request or the node and time limits may have set,
+    // so the engine is ready for its next search
+    fn end_search(&mut self) {
+        self.stop.store(false, Ordering::Relaxed);
+    }
+
+    // Lazy SMP: helper threads search the same position alongside this one, sharing only the
+    // transposition table, the node count and the stop flag. What they find reaches this thread
+    // through the table; the moves reported always come from this thread.
+    fn parallel_search<F: FnMut(usize, &[ScoredMove])>(&mut self, n_moves: usize, max_depth: usize, on_depth: F) -> Vec<ScoredMove> {
+        if self.threads == 1 {
+            return self.iterative_deepening(n_moves, max_depth, on_depth);
         }
-        let white_material = self.bitboard_material(self.board.white_pawns) * 1 +
-                                self.bitboard_material(self.board.white_knights) * 3 +
-                                self.bitboard_material(self.board.white_bishops) * 3 +
-                                self.bitboard_material(self.board.white_rooks) * 5 +
-                                self.bitboard_material(self.board.white_queens) * 9;
 
-        let black_material = self.bitboard_material(self.board.black_pawns) * 1 +
-                                self.bitboard_material(self.board.black_knights) * 3 +
-                                self.bitboard_material(self.board.black_bishops) * 3 +
-                                self.bitboard_material(self.board.black_rooks) * 5 +
-                                self.bitboard_material(self.board.black_queens) * 9;
+        thread::scope(|scope| {
+            for helper_index in 1..self.threads {
+                // The clone copies the evaluator too, pawn hash table included. That is on purpose:
+                // the table is only a few hundred kilobytes, and each thread needs one it can write
+                // to without locking, which starts out warm from this thread's copy.
+                let mut helper = self.clone();
+                scope.spawn(move || helper.helper_search(helper_index, max_depth));
+            }
+
+            let best_moves = self.iterative_deepening(n_moves, max_depth, on_depth);
 
-        white_material - black_material
+            // The helpers keep going until they are told to stop
+            self.stop.store(true, Ordering::Relaxed);
+            best_moves
+        })
     }
 
-    // Helper function to count the bits of a bitboard
-    fn bitboard_material(&self, bitboard: u64) -> i32 {
-        bitboard.count_ones() as i32
+    // The iterations of a helper thread, only run to fill the transposition table. Each helper
+    // skips its own pattern of depths, see HELPER_SKIP_SIZE.
+    fn helper_search(&mut self, helper_index: usize, max_depth: usize) {
+        let skip_size = HELPER_SKIP_SIZE[(helper_index - 1) % HELPER_SKIP_SIZE.len()];
+        let skip_phase = HELPER_SKIP_PHASE[(helper_index - 1) % HELPER_SKIP_PHASE.len()];
+
+        let mut best_move = None;
+        for depth in 1..=max_depth {
+            if (depth + skip_phase) / skip_size % 2 == 1 {
+                continue;
+            }
+            let scored_moves = self.search_root(depth, 1, best_move);
+            if self.is_stopped() {
+                break;
+            }
+            best_move = scored_moves.into_iter().max_by_key(|scored_move| scored_move.score).map(|scored_move| scored_move.mv);
+        }
     }
 
-    // Entry point for the search with iterative deepening
-    pub fn search_moves(&mut self, n_moves: usize, max_depth: usize) -> Vec<ScoredMove> {
+    fn iterative_deepening<F: FnMut(usize, &[ScoredMove])>(&mut self, n_moves: usize, max_depth: usize, mut on_depth: F) -> Vec<ScoredMove> {
         let mut best_moves: Vec<ScoredMove> = Vec::new();
 
         for depth in 1..=max_depth {
-            let mut scored_moves = self.depth_first_search_parallel(depth);
+            let mut scored_moves = self.search_root(depth, n_moves, best_moves.first().map(|scored_move| scored_move.mv));
 
             // Sort moves by score
-            scored_moves.sort_by(|a, b| b.score.cmp(&a.score));
+            scored_moves.sort_by_key(|scored_move| std::cmp::Reverse(scored_move.score));
+
+            // An interrupted iteration only holds the moves searched before the stop. It is not
+            // reported, and only used if we have nothing better, i.e. when stopped during depth 1.
+            if self.is_stopped() {
+                if best_moves.is_empty() {
+                    best_moves = scored_moves.into_iter().take(n_moves).collect();
+                }
+                if best_moves.is_empty() {
+                    best_moves.extend(self.fallback_move());
+                }
+                break;
+            }
 
             // Update the list of best moves if better moves are found at this depth
-            if !scored_moves.is_empty() {
-                best_moves = scored_moves.into_iter().take(n_moves).collect();
+            if scored_moves.is_empty() {
+                break; // No legal moves, nothing to search
             }
+            let best_move_changed = best_moves.first().is_some_and(|best| best.mv != scored_moves[0].mv);
+            best_moves = scored_moves.into_iter().take(n_moves).collect();
 
-            println!("Depth: {}, Best moves: {:?}", depth, best_moves);
+            on_depth(depth, &best_moves);
 
-            // Break early if the maximum depth is reached
-            if depth == max_depth {
+            // Think longer while the best move keeps changing, and don't start an iteration
+            // that has little chance of finishing in time
+            self.time_manager.update(best_move_changed);
+            if self.is_stopped() || !self.time_manager.can_start_iteration() {
                 break;
             }
         }
@@ -90,63 +374,367 @@ impl Engine {
         best_moves
     }
 
-    // // Depth-first search implementation
-    // fn depth_first_search(&mut self, depth: usize) -> Vec<ScoredMove> {
-    //     let legal_moves = self.board.generate_legal_moves();
-    //     let mut scored_moves = Vec::new();
+    // A move to play when the search was stopped before it finished searching a single one: the
+    // move from the transposition table if it has one, else the first legal move
+    fn fallback_move(&mut self) -> Option<ScoredMove> {
+        let legal_moves = self.board.generate_legal_moves();
+        let hash_move = self.tt().probe(self.board.hash).and_then(|entry| entry.best_move).filter(|mv| legal_moves.contains(mv));
+        let mv = hash_move.or(legal_moves.first().copied())?;
+        Some(ScoredMove::new(mv, self.static_eval(), vec![mv]))
+    }
+
+    // Handle that a background search can be stopped through
+    pub fn start_search(self, limits: SearchLimits) -> SearchHandle {
+        SearchHandle::start(self, limits)
+    }
 
-    //     for mv in legal_moves {
-    //         let undo_state = self.board.make_move(mv);
-    //         let score = -self.minimax(depth - 1, -i32::MAX, i32::MAX);
-    //         self.board.unmake_move(mv, undo_state);
+    fn is_stopped(&self) -> bool {
+        self.stop.load(Ordering::Relaxed)
+    }
 
-    //         scored_moves.push(ScoredMove::new(mv, score));
-    //     }
+    // Count a node and check whether the search has run out of nodes or time
+    fn check_limits(&self) -> bool {
+        if self.is_stopped() {
+            return true;
+        }
 
-    //     scored_moves
-    // }
+        let nodes = self.nodes.fetch_add(1, Ordering::Relaxed) + 1;
+        let out_of_nodes = self.node_limit.is_some_and(|limit| nodes >= limit);
+        let out_of_time = nodes.is_multiple_of(TIME_CHECK_INTERVAL) && self.deadline.is_some_and(|deadline| Instant::now() >= deadline);
+
+        if out_of_nodes || out_of_time {
+            self.stop.store(true, Ordering::Relaxed);
+            return true;
+        }
+
+        false
+    }
+
+    // Makes a move on the board during the search, keeping the evaluator informed
+    fn make_move(&mut self, mv: Move) -> UndoState {
+        let undo_state = self.board.make_move(mv);
+        self.evaluator.on_make_move(&self.board, mv);
+        undo_state
+    }
 
-    // Parallel depth-first search implementation
-    fn depth_first_search_parallel(&mut self, depth: usize) -> Vec<ScoredMove> {
+    fn unmake_move(&mut self, mv: Move, undo_state: UndoState) {
+        self.board.unmake_move(mv, undo_state);
+        self.evaluator.on_unmake_move(&self.board, mv);
+    }
+
+    // Records how deep the search has gone, for reporting
+    fn update_seldepth(&self, ply: usize) {
+        if ply > self.seldepth.load(Ordering::Relaxed) {
+            self.seldepth.fetch_max(ply, Ordering::Relaxed);
+        }
+    }
+
+    // Searches every root move, returning each with its score and principal variation.
+    // Moves are searched with a full window until `n_moves` of them have exact scores; the
+    // others only have to show whether they beat the worst of those. A stopped search returns
+    // the moves it finished searching before the stop.
+    fn search_root(&mut self, depth: usize, n_moves: usize, first_move: Option<Move>) -> Vec<ScoredMove> {
+        // Start with the best move of the previous iteration
         let legal_moves = self.board.generate_legal_moves();
+        let move_picker = MovePicker::new(&self.board, legal_moves, first_move, [None; 2], &self.history);
+
+        let n_moves = n_moves.max(1);
+        let mut scored_moves = Vec::new();
+        let mut best_scores: Vec<Score> = Vec::new(); // The best `n_moves` scores so far, highest first
+        for mv in move_picker {
+            let alpha = if best_scores.len() < n_moves { -Score::INFINITE } else { best_scores[n_moves - 1] };
+            let scored_move = self.search_root_move(mv, depth, alpha);
+            if self.is_stopped() {
+                break; // The move was not searched to the end, so its score means nothing
+            }
 
-        let pool = ThreadPoolBuilder::new().build().unwrap();
+            let index = best_scores.partition_point(|&score| score >= scored_move.score);
+            best_scores.insert(index, scored_move.score);
+            best_scores.truncate(n_moves);
 
-        let scored_moves: Vec<ScoredMove> = pool.install(|| {
-            legal_moves.par_iter().map(|&mv| {
-                let mut cloned_engine = self.clone();
-                let undo_state = cloned_engine.board.make_move(mv);
-                let score = cloned_engine.minimax(depth - 1, -i32::MAX, i32::MAX);
-                cloned_engine.board.unmake_move(mv, undo_state);
-                ScoredMove::new(mv, score)
-            }).collect()
-        });
+            scored_moves.push(scored_move);
+        }
 
         scored_moves
     }
 
-    // Minimax algorithm with alpha-beta pruning
-    fn minimax(&mut self, depth: usize, alpha: i32, beta: i32) -> i32 {
+    // Searches a single root move with the window (alpha, infinity). Unless alpha is still open,
+    // the move is first searched with a null window, and only searched again in full if it beats alpha.
+    fn search_root_move(&mut self, mv: Move, depth: usize, alpha: Score) -> ScoredMove {
+        let mut child_pv = Vec::new();
+        let undo_state = self.make_move(mv);
+        let mut score = alpha + 1;
+        if alpha > -Score::INFINITE {
+            score = -self.negamax(depth - 1, 1, -alpha - 1, -alpha, &mut child_pv);
+        }
+        if score > alpha {
+            score = -self.negamax(depth - 1, 1, -Score::INFINITE, -alpha, &mut child_pv);
+        }
+        self.unmake_move(mv, undo_state);
+
+        let mut pv = vec![mv];
+        pv.extend(child_pv);
+        ScoredMove::new(mv, score, pv)
+    }
+
+    // Negamax search with alpha-beta pruning. It fails soft, so the score returned can lie outside
+    // the (alpha, beta) window: at most alpha means no move reached alpha, at least beta means a
+    // move was good enough for a cutoff. `ply` is the distance from the root, for scoring mates,
+    // and `pv` receives the best line from this position when the score lands inside the window.
+    fn negamax(&mut self, depth: usize, ply: usize, alpha: Score, beta: Score, pv: &mut Vec<Move>) -> Score {
+        pv.clear();
+
+        if self.check_limits() {
+            return Score::DRAW; // The result is thrown away once the search is stopped
+        }
+        self.update_seldepth(ply);
+
+        // Going back to a position already seen, in the game or along this line, is a draw,
+        // and so is a position where neither side can mate
+        if self.board.is_repetition() || self.board.halfmove_clock >= 100 || self.board.is_insufficient_material() {
+            return Score::DRAW;
+        }
+
+        // Extensions can't take the search past the end of the ply tables
+        if ply >= MAX_PLY - 1 {
+            return self.static_eval();
+        }
+
+        // Being in check is forcing, so the search doesn't stop until the check is answered
+        let in_check = self.board.is_in_check(self.board.side_to_move);
+        let depth = if in_check && self.config.check_extensions { depth + 1 } else { depth };
+
         if depth == 0 {
-            return self.evaluate();
+            return self.quiescence(ply, alpha, beta);
+        }
+
+        // Only nodes searched with a null window are pruned; the principal variation is searched in full
+        let is_pv = beta.value() - alpha.value() > 1;
+
+        // Reuse what an earlier search found for this position. The table keeps no lines, so a
+        // cutoff on the principal variation would cut it short there; it only supplies the move.
+        let mut hash_move = None;
+        if let Some(entry) = self.tt().probe(self.board.hash) {
+            if !is_pv && entry.depth as usize >= depth {
+                let score = Score::from_tt(entry.score, ply);
+                match entry.bound {
+                    Bound::Exact => return score,
+                    Bound::Lower if score >= beta => return score,
+                    Bound::Upper if score <= alpha => return score,
+                    _ => {},
+                }
+            }
+            hash_move = entry.best_move;
         }
 
+        let legal_moves = self.board.generate_legal_moves();
+        if legal_moves.is_empty() {
+            // Checkmate or stalemate
+            return if in_check { Score::mated_in(ply) } else { Score::DRAW };
+        }
+
+        let static_eval = self.static_eval();
+
+        if !is_pv && !in_check && !beta.is_mate() {
+            // Reverse futility pruning: this close to the horizon, a position this far above beta
+            // is not going to drop below it
+            if self.config.reverse_futility
+                && depth <= REVERSE_FUTILITY_MAX_DEPTH
+                && static_eval - REVERSE_FUTILITY_MARGIN * depth as i32 >= beta
+            {
+                return static_eval;
+            }
+
+            // Null-move pruning: if passing still leaves the opponent unable to reach beta with a
+            // shallower search, a real move will almost always do at least as well
+            if self.config.null_move
+                && depth >= NULL_MOVE_MIN_DEPTH
+                && static_eval >= beta
+                && self.null_move_ply != Some(ply)
+                && self.board.has_non_pawn_material(self.board.side_to_move)
+            {
+                let reduction = NULL_MOVE_REDUCTION + depth / 6;
+                let mut child_pv = Vec::new();
+
+                let previous_null_move_ply = self.null_move_ply.replace(ply + 1);
+                let undo_state = self.board.make_null_move();
+                let score = -self.negamax(depth.saturating_sub(reduction + 1), ply + 1, -beta, -beta + 1, &mut child_pv);
+                self.board.unmake_null_move(undo_state);
+                self.null_move_ply = previous_null_move_ply;
+
+                if self.is_stopped() {
+                    return Score::DRAW;
+                }
+                // A mate found after passing is not a mate that can be trusted
+                if score >= beta {
+                    return if score.is_mate() { beta } else { score };
+                }
+            }
+        }
+
+        // Futility pruning: near the horizon, quiet moves can't bring a position far below alpha back up
+        let futile = self.config.futility
+            && !is_pv
+            && !in_check
+            && depth <= FUTILITY_MAX_DEPTH
+            && !alpha.is_mate()
+            && static_eval + FUTILITY_MARGIN * depth as i32 <= alpha;
+
+        let move_picker = MovePicker::new(&self.board, legal_moves, hash_move, self.killers.get(ply), &self.history);
+
+        let original_alpha = alpha;
         let mut alpha = alpha;
+        let mut best_score = -Score::INFINITE;
+        let mut best_move = None;
+        let mut child_pv = Vec::new();
+        for (move_index, mv) in move_picker.enumerate() {
+            let quiet = !self.board.is_capture(&mv) && mv.promotion.is_none();
+
+            let undo_state = self.make_move(mv);
+            let gives_check = self.board.is_in_check(self.board.side_to_move);
+
+            // The first move is always searched, so there is a score to return
+            if futile && quiet && !gives_check && move_index > 0 {
+                self.unmake_move(mv, undo_state);
+                continue;
+            }
+
+            // Late move reductions: with good move ordering, quiet moves late in the list rarely
+            // turn out best, so they are first searched shallower with a null window around alpha.
+            // Only one that beats alpha is searched again at full depth.
+            let reduction = if self.config.late_move_reductions
+                && depth >= LMR_MIN_DEPTH
+                && move_index >= LMR_MIN_MOVE
+                && quiet
+                && !in_check
+                && !gives_check
+            {
+                late_move_reduction(depth, move_index)
+            } else {
+                0
+            };
+
+            // Principal variation search: the first move is searched with the full window. Every
+            // other move only has to be shown no better than alpha, which a null window does much
+            // more cheaply; one that beats alpha anyway is searched again with the full window.
+            let score = if move_index == 0 {
+                -self.negamax(depth - 1, ply + 1, -beta, -alpha, &mut child_pv)
+            } else {
+                let mut score = alpha + 1;
+                if reduction > 0 {
+                    score = -self.negamax(depth - 1 - reduction, ply + 1, -alpha - 1, -alpha, &mut child_pv);
+                }
+                if score > alpha {
+                    score = -self.negamax(depth - 1, ply + 1, -alpha - 1, -alpha, &mut child_pv);
+                }
+                if score > alpha && score < beta {
+                    score = -self.negamax(depth - 1, ply + 1, -beta, -alpha, &mut child_pv);
+                }
+                score
+            };
+            self.unmake_move(mv, undo_state);
+
+            if score > best_score {
+                best_score = score;
+                best_move = Some(mv);
+
+                if score > alpha {
+                    alpha = score;
+                    pv.clear();
+                    pv.push(mv);
+                    pv.extend_from_slice(&child_pv);
+                }
+            }
+
+            if alpha >= beta {
+                // Remember quiet moves that refute a position, to try them early elsewhere
+                if quiet {
+                    self.killers.store(ply, mv);
+                    self.history.reward(self.board.side_to_move, mv, depth);
+                }
+                break; // Beta cutoff
+            }
+        }
+
+        // A stopped search returns garbage, don't let it into the table
+        if self.is_stopped() {
+            return Score::DRAW;
+        }
+
+        let bound = if best_score <= original_alpha {
+            Bound::Upper
+        } else if best_score >= beta {
+            Bound::Lower
+        } else {
+            Bound::Exact
+        };
+        self.tt().store(self.board.hash, depth, bound, best_score.to_tt(ply), best_move);
+
+        best_score
+    }
+
+    // Quiescence search: keep resolving captures and promotions until the position is quiet,
+    // so the evaluation is never taken in the middle of an exchange.
+    fn quiescence(&mut self, ply: usize, alpha: Score, beta: Score) -> Score {
+        if self.check_limits() {
+            return Score::DRAW; // The result is thrown away once the search is stopped
+        }
+        self.update_seldepth(ply);
+
+        // Captures can trade down into a dead draw
+        if self.board.is_insufficient_material() {
+            return Score::DRAW;
+        }
+
+        if ply >= MAX_PLY - 1 {
+            return self.static_eval();
+        }
+
+        let in_check = self.board.is_in_check(self.board.side_to_move);
         let legal_moves = self.board.generate_legal_moves();
+        if legal_moves.is_empty() {
+            return if in_check { Score::mated_in(ply) } else { Score::DRAW }; // Checkmate or stalemate
+        }
+
+        let mut alpha = alpha;
+        let mut best_score = -Score::INFINITE;
+        let mut stand_pat = Score::DRAW;
+
+        // When in check every evasion is searched, since standing pat is not an option
+        if !in_check {
+            // Stand pat: the side to move can usually do at least as well as the static evaluation
+            stand_pat = self.static_eval();
+            if stand_pat >= beta {
+                return stand_pat;
+            }
+            alpha = alpha.max(stand_pat);
+            best_score = stand_pat;
+        }
 
-        let mut best_score = -i32::MAX;
+        // Look at the most valuable victims first, taken by the least valuable attackers
+        let mut moves: Vec<(Move, i32)> = Vec::new();
         for mv in legal_moves {
-            let undo_state = self.board.make_move(mv);
-            
-            // Get score from minimax and safely negate it
-            let mut score = self.minimax(depth - 1, -alpha, -beta);
-            if score == i32::MIN {
-                score = i32::MAX; // or handle this some other way
-            } else {
-                score = -score;
+            let captured_value = self.board.captured_piece_type(&mv).map_or(0, |piece_type| piece_type.value());
+            if in_check || captured_value > 0 || mv.promotion.is_some() {
+                moves.push((mv, captured_value));
             }
+        }
+        moves.sort_by_cached_key(|&(mv, _)| std::cmp::Reverse(mvv_lva(&self.board, mv)));
 
-            self.board.unmake_move(mv, undo_state);
+        for (mv, captured_value) in moves {
+            // Delta pruning: skip captures that cannot raise alpha even with a generous margin
+            if !in_check && mv.promotion.is_none() && stand_pat + captured_value + DELTA_MARGIN < alpha {
+                continue;
+            }
+
+            // Captures that lose material in the exchange are very unlikely to help
+            if !in_check && mv.promotion.is_none() && !self.board.see_ge(mv, 0) {
+                continue;
+            }
+
+            let undo_state = self.make_move(mv);
+            let score = -self.quiescence(ply + 1, -beta, -alpha);
+            self.unmake_move(mv, undo_state);
 
             best_score = best_score.max(score);
             alpha = alpha.max(score);
@@ -228,7 +816,7 @@ impl Engine {
 
         // sum all counts in top level move counts and print it
         let mut total_nodes = 0;
-        for (_, count) in &top_level_moves_count {
+        for count in top_level_moves_count.values() {
             total_nodes += count;
         }
         println!("Total nodes: {}", total_nodes);
@@ -254,4 +842,103 @@ impl Engine {
         total_moves
     }
 
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The queen can take d5, but the c6 pawn takes her back
+    const DEFENDED_PAWN: &str = "4k3/8/2p5/3p4/8/8/8/3QK3 w - - 0 1";
+
+    fn engine(fen: &str) -> Engine {
+        let mut engine = Engine::with_board(Some(Board::from_fen(fen).unwrap()));
+        engine.begin_search(&SearchLimits::default());
+        engine
+    }
+
+    // Quiescence score of the position, and how many nodes it took
+    fn quiescence(fen: &str, alpha: Score, beta: Score) -> (Score, u64) {
+        let mut engine = engine(fen);
+        let score = engine.quiescence(0, alpha, beta);
+        (score, engine.nodes.load(Ordering::Relaxed))
+    }
+
+    // Stopped during the first iteration, the search reports nothing but still has a legal move
+    #[test]
+    fn stop_during_depth_one_plays_a_legal_move_without_reporting() {
+        let kiwipete = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        for nodes in [1, 5, 20] {
+            let mut engine = Engine::with_board(Some(Board::from_fen(kiwipete).unwrap()));
+            let mut reported = Vec::new();
+            let limits = SearchLimits { nodes: Some(nodes), ..SearchLimits::default() };
+            let best_move = engine.search(&limits, |info| reported.push(info.depth)).unwrap();
+
+            assert!(reported.is_empty(), "reported depths {:?} with {} nodes", reported, nodes);
+            assert!(engine.board.generate_legal_moves().contains(&best_move.mv));
+        }
+
+        // A stop that arrives before the search starts
+        let mut engine = Engine::with_board(Some(Board::from_fen(kiwipete).unwrap()));
+        engine.stop_flag().store(true, Ordering::Relaxed);
+        let mut reported = false;
+        let best_move = engine.search(&SearchLimits::default(), |_| reported = true).unwrap();
+        assert!(!reported);
+        assert!(engine.board.generate_legal_moves().contains(&best_move.mv));
+    }
+
+    #[test]
+    fn quiescence_sees_past_the_horizon() {
+        // Judged by the evaluation right after it, taking the pawn is the best move
+        let mut engine = engine(DEFENDED_PAWN);
+        let grab = engine.board.parse_uci_move("d1d5").unwrap();
+        let static_score = |engine: &mut Engine, mv: Move| {
+            let undo_state = engine.make_move(mv);
+            let score = -engine.static_eval();
+            engine.unmake_move(mv, undo_state);
+            score
+        };
+        let grab_score = static_score(&mut engine, grab);
+        for mv in engine.board.generate_legal_moves() {
+            assert!(static_score(&mut engine, mv) <= grab_score);
+        }
+
+        // A depth 1 search follows the captures to the end and sees the queen is lost
+        let best_move = engine.search(&SearchLimits::depth(1), |_| {}).unwrap();
+        assert_ne!(best_move.mv, grab);
+        assert!(best_move.score.value() > -PieceType::Queen.value() / 2);
+    }
+
+    #[test]
+    fn losing_captures_are_not_searched() {
+        // Qxd5 loses the queen for a pawn, so only the root is searched
+        let (score, nodes) = quiescence(DEFENDED_PAWN, -Score::INFINITE, Score::INFINITE);
+        assert_eq!(nodes, 1);
+        assert_eq!(score, engine(DEFENDED_PAWN).static_eval());
+    }
+
+    #[test]
+    fn captures_that_cannot_reach_alpha_are_not_searched() {
+        // Taking the free pawn is searched, unless a pawn is not nearly enough
+        let fen = "4k3/8/8/3p4/8/8/8/3QK3 w - - 0 1";
+        let stand_pat = engine(fen).static_eval();
+        assert_eq!(quiescence(fen, -Score::INFINITE, Score::INFINITE).1, 2);
+
+        let alpha = stand_pat + PieceType::Pawn.value() + DELTA_MARGIN + 1;
+        let (score, nodes) = quiescence(fen, alpha, alpha + 1);
+        assert_eq!(nodes, 1);
+        assert_eq!(score, stand_pat);
+    }
+
+    #[test]
+    fn quiescence_searches_every_evasion_when_in_check() {
+        // The rook gives check; none of the king's replies is a capture, and standing pat is not allowed
+        let (score, nodes) = quiescence("4k3/8/8/8/8/8/8/r3K3 w - - 0 1", -Score::INFINITE, Score::INFINITE);
+        assert!(!score.is_mate(), "{}", score);
+        assert!(nodes > 1);
+
+        // Rxd8 is a capture, and the replies to its check show it is mate
+        let (score, _) = quiescence("3r2k1/5ppp/8/8/8/8/8/3RK3 w - - 0 1", -Score::INFINITE, Score::INFINITE);
+        assert_eq!(score, Score::mate_in(1));
+    }
+}
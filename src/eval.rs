@@ -0,0 +1,651 @@
+// eval.rs
+// Static evaluation. Every term is scored twice, once for the middlegame and once for the endgame,
+// and the two are blended by how much material is left on the board. That way a king is kept
+// safe behind its pawns while the queens are on, and marched to the centre once they are gone.
+use std::fmt;
+use std::ops::{Add, AddAssign, Mul, Neg, Sub};
+
+use crate::attacks::{bishop_attacks, king_attacks, knight_attacks, pawn_attacks, queen_attacks, rook_attacks};
+use crate::board::{file_mask, rank_mask, Board, BLACK, WHITE};
+use crate::pawns::{evaluate_pawns, passed_pawn_king_proximity, PawnEntry, PawnTable};
+use crate::piece::PieceType;
+use crate::utils::{get_file, get_rank, pop_lsb};
+
+// Game phase weight of each piece type; the starting position adds up to MAX_PHASE
+const KNIGHT_PHASE: i32 = 1;
+const BISHOP_PHASE: i32 = 1;
+const ROOK_PHASE: i32 = 2;
+const QUEEN_PHASE: i32 = 4;
+pub const MAX_PHASE: i32 = 24;
+
+// Number of safe squares a piece of each kind, knight to queen, typically reaches. Mobility
+// is scored relative to these, so a piece with an average number of moves counts for nothing extra.
+const MOBILITY_BASELINE: [i32; 4] = [4, 6, 6, 13];
+
+// A score with a middlegame and an endgame part
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TaperedScore {
+    pub mg: i32,
+    pub eg: i32,
+}
+
+impl TaperedScore {
+    pub const fn new(mg: i32, eg: i32) -> Self {
+        Self { mg, eg }
+    }
+
+    // Blends the two parts by the game phase, from MAX_PHASE (all pieces on) down to 0 (only pawns and kings)
+    pub fn taper(self, phase: i32) -> i32 {
+        let phase = phase.clamp(0, MAX_PHASE);
+        (self.mg * phase + self.eg * (MAX_PHASE - phase)) / MAX_PHASE
+    }
+}
+
+impl Add for TaperedScore {
+    type Output = TaperedScore;
+
+    fn add(self, other: TaperedScore) -> TaperedScore {
+        TaperedScore::new(self.mg + other.mg, self.eg + other.eg)
+    }
+}
+
+impl AddAssign for TaperedScore {
+    fn add_assign(&mut self, other: TaperedScore) {
+        *self = *self + other;
+    }
+}
+
+impl Sub for TaperedScore {
+    type Output = TaperedScore;
+
+    fn sub(self, other: TaperedScore) -> TaperedScore {
+        TaperedScore::new(self.mg - other.mg, self.eg - other.eg)
+    }
+}
+
+impl Neg for TaperedScore {
+    type Output = TaperedScore;
+
+    fn neg(self) -> TaperedScore {
+        TaperedScore::new(-self.mg, -self.eg)
+    }
+}
+
+impl Mul<i32> for TaperedScore {
+    type Output = TaperedScore;
+
+    fn mul(self, factor: i32) -> TaperedScore {
+        TaperedScore::new(self.mg * factor, self.eg * factor)
+    }
+}
+
+// Weights of the evaluation terms that aren't tables, gathered in one place so they can be tuned
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EvalWeights {
+    pub mobility: [TaperedScore; 4],       // Per safe square above the baseline, knight to queen
+    pub pawn_shield: [TaperedScore; 2],    // Per own pawn one and two ranks in front of the king, on its file or next to it
+    pub semi_open_file: TaperedScore,      // Per file at or next to the king without a pawn of its own
+    pub open_file: TaperedScore,           // Per file at or next to the king without any pawn
+    pub king_attack_weights: [i32; 4],     // Per square of the king zone attacked, knight to queen
+    pub king_attack_scale: [i32; 8],       // Percentage of the attack weight that counts, by number of attackers
+}
+
+impl Default for EvalWeights {
+    fn default() -> Self {
+        Self {
+            mobility: [
+                TaperedScore::new(4, 4),
+                TaperedScore::new(5, 5),
+                TaperedScore::new(2, 4),
+                TaperedScore::new(1, 2),
+            ],
+            pawn_shield: [TaperedScore::new(12, 0), TaperedScore::new(6, 0)],
+            semi_open_file: TaperedScore::new(-15, 0),
+            open_file: TaperedScore::new(-25, 0),
+            king_attack_weights: [20, 20, 40, 80],
+            // A lone attacker rarely gets anywhere, several together do
+            king_attack_scale: [0, 0, 50, 75, 88, 94, 97, 99],
+        }
+    }
+}
+
+// Piece values and piece-square tables are PeSTO's, tuned by Ronald Friederich.
+// The king's value doesn't matter, both sides always have one.
+const PIECE_VALUES: [TaperedScore; 6] = [
+    TaperedScore::new(82, 94),
+    TaperedScore::new(337, 281),
+    TaperedScore::new(365, 297),
+    TaperedScore::new(477, 512),
+    TaperedScore::new(1025, 936),
+    TaperedScore::new(0, 0),
+];
+
+// The tables are laid out the way a board is printed for White: a8 first, h1 last
+#[rustfmt::skip]
+const MG_PAWN_TABLE: [i32; 64] = [
+      0,   0,   0,   0,   0,   0,   0,   0,
+     98, 134,  61,  95,  68, 126,  34, -11,
+     -6,   7,  26,  31,  65,  56,  25, -20,
+    -14,  13,   6,  21,  23,  12,  17, -23,
+    -27,  -2,  -5,  12,  17,   6,  10, -25,
+    -26,  -4,  -4, -10,   3,   3,  33, -12,
+    -35,  -1, -20, -23, -15,  24,  38, -22,
+      0,   0,   0,   0,   0,   0,   0,   0,
+];
+
+#[rustfmt::skip]
+const EG_PAWN_TABLE: [i32; 64] = [
+      0,   0,   0,   0,   0,   0,   0,   0,
+    178, 173, 158, 134, 147, 132, 165, 187,
+     94, 100,  85,  67,  56,  53,  82,  84,
+     32,  24,  13,   5,  -2,   4,  17,  17,
+     13,   9,  -3,  -7,  -7,  -8,   3,  -1,
+      4,   7,  -6,   1,   0,  -5,  -1,  -8,
+     13,   8,   8,  10,  13,   0,   2,  -7,
+      0,   0,   0,   0,   0,   0,   0,   0,
+];
+
+#[rustfmt::skip]
+const MG_KNIGHT_TABLE: [i32; 64] = [
+    -167, -89, -34, -49,  61, -97, -15, -107,
+     -73, -41,  72,  36,  23,  62,   7,  -17,
+     -47,  60,  37,  65,  84, 129,  73,   44,
+      -9,  17,  19,  53,  37,  69,  18,   22,
+     -13,   4,  16,  13,  28,  19,  21,   -8,
+     -23,  -9,  12,  10,  19,  17,  25,  -16,
+     -29, -53, -12,  -3,  -1,  18, -14,  -19,
+    -105, -21, -58, -33, -17, -28, -19,  -23,
+];
+
+#[rustfmt::skip]
+const EG_KNIGHT_TABLE: [i32; 64] = [
+    -58, -38, -13, -28, -31, -27, -63, -99,
+    -25,  -8, -25,  -2,  -9, -25, -24, -52,
+    -24, -20,  10,   9,  -1,  -9, -19, -41,
+    -17,   3,  22,  22,  22,  11,   8, -18,
+    -18,  -6,  16,  25,  16,  17,   4, -18,
+    -23,  -3,  -1,  15,  10,  -3, -20, -22,
+    -42, -20, -10,  -5,  -2, -20, -23, -44,
+    -29, -51, -23, -15, -22, -18, -50, -64,
+];
+
+#[rustfmt::skip]
+const MG_BISHOP_TABLE: [i32; 64] = [
+    -29,   4, -82, -37, -25, -42,   7,  -8,
+    -26,  16, -18, -13,  30,  59,  18, -47,
+    -16,  37,  43,  40,  35,  50,  37,  -2,
+     -4,   5,  19,  50,  37,  37,   7,  -2,
+     -6,  13,  13,  26,  34,  12,  10,   4,
+      0,  15,  15,  15,  14,  27,  18,  10,
+      4,  15,  16,   0,   7,  21,  33,   1,
+    -33,  -3, -14, -21, -13, -12, -39, -21,
+];
+
+#[rustfmt::skip]
+const EG_BISHOP_TABLE: [i32; 64] = [
+    -14, -21, -11,  -8,  -7,  -9, -17, -24,
+     -8,  -4,   7, -12,  -3, -13,  -4, -14,
+      2,  -8,   0,  -1,  -2,   6,   0,   4,
+     -3,   9,  12,   9,  14,  10,   3,   2,
+     -6,   3,  13,  19,   7,  10,  -3,  -9,
+    -12,  -3,   8,  10,  13,   3,  -7, -15,
+    -14, -18,  -7,  -1,   4,  -9, -15, -27,
+    -23,  -9, -23,  -5,  -9, -16,  -5, -17,
+];
+
+#[rustfmt::skip]
+const MG_ROOK_TABLE: [i32; 64] = [
+     32,  42,  32,  51,  63,   9,  31,  43,
+     27,  32,  58,  62,  80,  67,  26,  44,
+     -5,  19,  26,  36,  17,  45,  61,  16,
+    -24, -11,   7,  26,  24,  35,  -8, -20,
+    -36, -26, -12,  -1,   9,  -7,   6, -23,
+    -45, -25, -16, -17,   3,   0,  -5, -33,
+    -44, -16, -20,  -9,  -1,  11,  -6, -71,
+    -19, -13,   1,  17,  16,   7, -37, -26,
+];
+
+#[rustfmt::skip]
+const EG_ROOK_TABLE: [i32; 64] = [
+     13,  10,  18,  15,  12,  12,   8,   5,
+     11,  13,  13,  11,  -3,   3,   8,   3,
+      7,   7,   7,   5,   4,  -3,  -5,  -3,
+      4,   3,  13,   1,   2,   1,  -1,   2,
+      3,   5,   8,   4,  -5,  -6,  -8, -11,
+     -4,   0,  -5,  -1,  -7, -12,  -8, -16,
+     -6,  -6,   0,   2,  -9,  -9, -11,  -3,
+     -9,   2,   3,  -1,  -5, -13,   4, -20,
+];
+
+#[rustfmt::skip]
+const MG_QUEEN_TABLE: [i32; 64] = [
+    -28,   0,  29,  12,  59,  44,  43,  45,
+    -24, -39,  -5,   1, -16,  57,  28,  54,
+    -13, -17,   7,   8,  29,  56,  47,  57,
+    -27, -27, -16, -16,  -1,  17,  -2,   1,
+     -9, -26,  -9, -10,  -2,  -4,   3,  -3,
+    -14,   2, -11,  -2,  -5,   2,  14,   5,
+    -35,  -8,  11,   2,   8,  15,  -3,   1,
+     -1, -18,  -9,  10, -15, -25, -31, -50,
+];
+
+#[rustfmt::skip]
+const EG_QUEEN_TABLE: [i32; 64] = [
+     -9,  22,  22,  27,  27,  19,  10,  20,
+    -17,  20,  32,  41,  58,  25,  30,   0,
+    -20,   6,   9,  49,  47,  35,  19,   9,
+      3,  22,  24,  45,  57,  40,  57,  36,
+    -18,  28,  19,  47,  31,  34,  39,  23,
+    -16, -27,  15,   6,   9,  17,  10,   5,
+    -22, -23, -30, -16, -16, -23, -36, -32,
+    -33, -28, -22, -43,  -5, -32, -20, -41,
+];
+
+#[rustfmt::skip]
+const MG_KING_TABLE: [i32; 64] = [
+    -65,  23,  16, -15, -56, -34,   2,  13,
+     29,  -1, -20,  -7,  -8,  -4, -38, -29,
+     -9,  24,   2, -16, -20,   6,  22, -22,
+    -17, -20, -12, -27, -30, -25, -14, -36,
+    -49,  -1, -27, -39, -46, -44, -33, -51,
+    -14, -14, -22, -46, -44, -30, -15, -27,
+      1,   7,  -8, -64, -43, -16,   9,   8,
+    -15,  36,  12, -54,   8, -28,  24,  14,
+];
+
+#[rustfmt::skip]
+const EG_KING_TABLE: [i32; 64] = [
+    -74, -35, -18, -18, -11,  15,   4, -17,
+    -12,  17,  14,  17,  17,  38,  23,  11,
+     10,  17,  23,  15,  20,  45,  44,  13,
+     -8,  22,  24,  27,  26,  33,  26,   3,
+    -18,  -4,  21,  24,  27,  23,   9, -11,
+    -19,  -3,  11,  21,  23,  16,   7,  -9,
+    -27, -11,   4,  13,  14,   4,  -5, -17,
+    -53, -34, -21, -11, -28, -14, -24, -43,
+];
+
+const PIECE_TYPES: [PieceType; 6] = [
+    PieceType::Pawn,
+    PieceType::Knight,
+    PieceType::Bishop,
+    PieceType::Rook,
+    PieceType::Queen,
+    PieceType::King,
+];
+
+fn tables(piece_type: PieceType) -> (&'static [i32; 64], &'static [i32; 64]) {
+    match piece_type {
+        PieceType::Pawn => (&MG_PAWN_TABLE, &EG_PAWN_TABLE),
+        PieceType::Knight => (&MG_KNIGHT_TABLE, &EG_KNIGHT_TABLE),
+        PieceType::Bishop => (&MG_BISHOP_TABLE, &EG_BISHOP_TABLE),
+        PieceType::Rook => (&MG_ROOK_TABLE, &EG_ROOK_TABLE),
+        PieceType::Queen => (&MG_QUEEN_TABLE, &EG_QUEEN_TABLE),
+        PieceType::King => (&MG_KING_TABLE, &EG_KING_TABLE),
+    }
+}
+
+// Piece-square bonus for a piece of the given side. Square 0 is a1 while the tables start at a8,
+// so White's squares are flipped vertically and Black's, seen from its own side, are used as they are.
+pub fn piece_square(piece_type: PieceType, side: bool, square: u8) -> TaperedScore {
+    let index = if side == WHITE { square as usize ^ 56 } else { square as usize };
+    let (mg_table, eg_table) = tables(piece_type);
+    TaperedScore::new(mg_table[index], eg_table[index])
+}
+
+// How far the game is from the endgame, MAX_PHASE with every piece on the board and 0 with
+// only pawns and kings left. Promotions can push the count past MAX_PHASE, so it is capped.
+pub fn game_phase(board: &Board) -> i32 {
+    let phase: i32 = [WHITE, BLACK]
+        .iter()
+        .map(|&side| {
+            board.pieces(PieceType::Knight, side).count_ones() as i32 * KNIGHT_PHASE
+                + board.pieces(PieceType::Bishop, side).count_ones() as i32 * BISHOP_PHASE
+                + board.pieces(PieceType::Rook, side).count_ones() as i32 * ROOK_PHASE
+                + board.pieces(PieceType::Queen, side).count_ones() as i32 * QUEEN_PHASE
+        })
+        .sum();
+    phase.min(MAX_PHASE)
+}
+
+// Material of one side
+pub fn material(board: &Board, side: bool) -> TaperedScore {
+    PIECE_TYPES.iter().fold(TaperedScore::default(), |total, &piece_type| {
+        total + PIECE_VALUES[piece_type.index()] * board.pieces(piece_type, side).count_ones() as i32
+    })
+}
+
+// Piece-square bonuses of one side
+pub fn piece_squares(board: &Board, side: bool) -> TaperedScore {
+    let mut total = TaperedScore::default();
+    for &piece_type in &PIECE_TYPES {
+        let mut pieces = board.pieces(piece_type, side);
+        while pieces != 0 {
+            total += piece_square(piece_type, side, pop_lsb(&mut pieces));
+        }
+    }
+    total
+}
+
+// Squares attacked by a knight, bishop, rook or queen
+fn piece_attacks(piece_type: PieceType, square: u8, occupancy: u64) -> u64 {
+    match piece_type {
+        PieceType::Knight => knight_attacks(square),
+        PieceType::Bishop => bishop_attacks(square, occupancy),
+        PieceType::Rook => rook_attacks(square, occupancy),
+        PieceType::Queen => queen_attacks(square, occupancy),
+        PieceType::Pawn | PieceType::King => panic!("no sliding or knight attacks for {:?}", piece_type),
+    }
+}
+
+// Every square attacked by the pawns of one side
+fn pawn_attack_span(board: &Board, side: bool) -> u64 {
+    let mut attacked = 0;
+    let mut pawns = board.pieces(PieceType::Pawn, side);
+    while pawns != 0 {
+        attacked |= pawn_attacks(pop_lsb(&mut pawns), side);
+    }
+    attacked
+}
+
+// Mobility of one side: how many squares its pieces can go to that aren't taken by its own pieces
+// or covered by enemy pawns
+pub fn mobility(board: &Board, side: bool, weights: &EvalWeights) -> TaperedScore {
+    let occupancy = board.occupancy();
+    let safe_squares = !board.side_occupancy(side) & !pawn_attack_span(board, !side);
+
+    let mut total = TaperedScore::default();
+    for (index, &piece_type) in PIECE_TYPES[1..5].iter().enumerate() {
+        let mut pieces = board.pieces(piece_type, side);
+        while pieces != 0 {
+            let moves = (piece_attacks(piece_type, pop_lsb(&mut pieces), occupancy) & safe_squares).count_ones() as i32;
+            total += weights.mobility[index] * (moves - MOBILITY_BASELINE[index]);
+        }
+    }
+    total
+}
+
+// Safety of one side's king: the pawns in front of it, open files leading to it, and how many
+// enemy pieces bear down on the squares around it
+pub fn king_safety(board: &Board, side: bool, weights: &EvalWeights) -> TaperedScore {
+    let king = board.pieces(PieceType::King, side).trailing_zeros() as u8;
+    let king_file = get_file(king);
+    let king_rank = get_rank(king) as i32;
+    let own_pawns = board.pieces(PieceType::Pawn, side);
+    let all_pawns = own_pawns | board.pieces(PieceType::Pawn, !side);
+    let forward = if side == WHITE { 1 } else { -1 };
+
+    let mut total = TaperedScore::default();
+    for file in king_file.saturating_sub(1)..=(king_file + 1).min(7) {
+        let file_squares = file_mask(file);
+
+        for (distance, &bonus) in weights.pawn_shield.iter().enumerate() {
+            let rank = king_rank + forward * (distance as i32 + 1);
+            if (0..8).contains(&rank) && own_pawns & file_squares & rank_mask(rank as u8) != 0 {
+                total += bonus;
+            }
+        }
+
+        if all_pawns & file_squares == 0 {
+            total += weights.open_file;
+        } else if own_pawns & file_squares == 0 {
+            total += weights.semi_open_file;
+        }
+    }
+
+    // Every enemy piece attacking the king zone adds to the danger, but the danger only really
+    // counts once several pieces join in
+    let zone = king_attacks(king) | 1u64 << king;
+    let occupancy = board.occupancy();
+    let mut attackers = 0;
+    let mut attack_weight = 0;
+    for (index, &piece_type) in PIECE_TYPES[1..5].iter().enumerate() {
+        let mut pieces = board.pieces(piece_type, !side);
+        while pieces != 0 {
+            let attacked = piece_attacks(piece_type, pop_lsb(&mut pieces), occupancy) & zone;
+            if attacked != 0 {
+                attackers += 1;
+                attack_weight += weights.king_attack_weights[index] * attacked.count_ones() as i32;
+            }
+        }
+    }
+    let danger = attack_weight * weights.king_attack_scale[attackers.min(7)] / 100;
+    total += TaperedScore::new(-danger, -danger / 4);
+
+    total
+}
+
+// The evaluation terms of one side, before tapering
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SideTerms {
+    pub material: TaperedScore,
+    pub piece_squares: TaperedScore,
+    pub pawns: TaperedScore, // Pawn structure, and the king proximity of passed pawns
+    pub mobility: TaperedScore,
+    pub king_safety: TaperedScore,
+}
+
+impl SideTerms {
+    fn new(board: &Board, side: bool, pawns: &PawnEntry, weights: &EvalWeights) -> Self {
+        Self {
+            material: material(board, side),
+            piece_squares: piece_squares(board, side),
+            pawns: pawns.score(side) + passed_pawn_king_proximity(board, pawns, side),
+            mobility: mobility(board, side, weights),
+            king_safety: king_safety(board, side, weights),
+        }
+    }
+
+    pub fn total(&self) -> TaperedScore {
+        self.material + self.piece_squares + self.pawns + self.mobility + self.king_safety
+    }
+}
+
+// Every term of an evaluation, per side, with the game phase they are blended by.
+// Printed, it is a table of the whole breakdown.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EvalTrace {
+    pub white: SideTerms,
+    pub black: SideTerms,
+    pub phase: i32,
+    pub side_to_move: bool,
+}
+
+impl EvalTrace {
+    fn new(board: &Board, pawns: &PawnEntry, weights: &EvalWeights) -> Self {
+        Self {
+            white: SideTerms::new(board, WHITE, pawns, weights),
+            black: SideTerms::new(board, BLACK, pawns, weights),
+            phase: game_phase(board),
+            side_to_move: board.side_to_move,
+        }
+    }
+
+    // The tapered score in centipawns from White's point of view
+    pub fn white_score(&self) -> i32 {
+        (self.white.total() - self.black.total()).taper(self.phase)
+    }
+
+    // The tapered score in centipawns from the point of view of the side to move, as the search sees it
+    pub fn score(&self) -> i32 {
+        if self.side_to_move == WHITE { self.white_score() } else { -self.white_score() }
+    }
+}
+
+impl fmt::Display for EvalTrace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let rule = "---------------+-----------------+-----------------+----------------";
+        let row = |f: &mut fmt::Formatter<'_>, name: &str, white: TaperedScore, black: TaperedScore| {
+            let total = white - black;
+            writeln!(
+                f,
+                "{:<15}| {:>6}  {:>6}  | {:>6}  {:>6}  | {:>6}  {:>6}",
+                name, white.mg, white.eg, black.mg, black.eg, total.mg, total.eg
+            )
+        };
+
+        let header = format!("{:<15}| {:^15} | {:^15} | {:^15}", "Term", "White", "Black", "Total");
+        writeln!(f, "{}", header.trim_end())?;
+        writeln!(f, "{:<15}| {:>6}  {:>6}  | {:>6}  {:>6}  | {:>6}  {:>6}", "", "MG", "EG", "MG", "EG", "MG", "EG")?;
+        writeln!(f, "{}", rule)?;
+        row(f, "Material", self.white.material, self.black.material)?;
+        row(f, "Piece squares", self.white.piece_squares, self.black.piece_squares)?;
+        row(f, "Pawns", self.white.pawns, self.black.pawns)?;
+        row(f, "Mobility", self.white.mobility, self.black.mobility)?;
+        row(f, "King safety", self.white.king_safety, self.black.king_safety)?;
+        writeln!(f, "{}", rule)?;
+        row(f, "Total", self.white.total(), self.black.total())?;
+        writeln!(f)?;
+        writeln!(f, "Phase: {} of {} (0 is a pawn endgame)", self.phase, MAX_PHASE)?;
+        write!(
+            f,
+            "Evaluation: {} cp for White, {} cp for the side to move ({})",
+            self.white_score(),
+            self.score(),
+            if self.side_to_move == WHITE { "White" } else { "Black" }
+        )
+    }
+}
+
+// Evaluation of the position in centipawns, from the point of view of the side to move.
+// Pawn structures are looked up in, and added to, the pawn hash table.
+pub fn evaluate(board: &Board, pawn_table: &mut PawnTable, weights: &EvalWeights) -> i32 {
+    EvalTrace::new(board, &pawn_table.probe(board), weights).score()
+}
+
+// The evaluation of the position broken down into its terms. This is the same evaluation
+// `evaluate` gives, only without the pawn hash table.
+pub fn trace(board: &Board, weights: &EvalWeights) -> EvalTrace {
+    EvalTrace::new(board, &evaluate_pawns(board), weights)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POSITIONS: [&str; 6] = [
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+        "rnbqkb1r/pp1p1ppp/5n2/2pPp3/8/8/PPP1PPPP/RNBQKBNR w KQkq e6 0 4",
+        "8/5pk1/6p1/1P5p/8/6P1/5PK1/8 b - - 0 50",
+    ];
+
+    // The same position with the colours swapped: the board turned upside down, white pieces
+    // made black and the other way round, and the other side to move
+    fn mirror(fen: &str) -> String {
+        let fields: Vec<&str> = fen.split_whitespace().collect();
+        let swap_case = |text: &str| -> String {
+            text.chars().map(|c| if c.is_ascii_uppercase() { c.to_ascii_lowercase() } else { c.to_ascii_uppercase() }).collect()
+        };
+
+        let placement: Vec<String> = fields[0].split('/').rev().map(swap_case).collect();
+        let side_to_move = if fields[1] == "w" { "b" } else { "w" };
+        let en_passant = match fields[3].as_bytes() {
+            [file, rank] => format!("{}{}", *file as char, (b'1' + b'8' - rank) as char),
+            _ => "-".to_string(),
+        };
+        format!("{} {} {} {} {} {}", placement.join("/"), side_to_move, swap_case(fields[2]), en_passant, fields[4], fields[5])
+    }
+
+    #[test]
+    fn colours_are_evaluated_alike() {
+        let weights = EvalWeights::default();
+        for fen in POSITIONS {
+            let board = Board::from_fen(fen).unwrap();
+            let mirrored = Board::from_fen(&mirror(fen)).unwrap();
+
+            // The side to move is just as well off in both, so White's score changes sign
+            let score = evaluate(&board, &mut PawnTable::default(), &weights);
+            assert_eq!(score, evaluate(&mirrored, &mut PawnTable::default(), &weights), "{}", fen);
+            assert_eq!(trace(&board, &weights).white_score(), -trace(&mirrored, &weights).white_score(), "{}", fen);
+            assert_eq!(trace(&board, &weights).white, trace(&mirrored, &weights).black, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn pawn_table_gives_the_uncached_evaluation() {
+        // One table for every position of a short walk through each of them, so the cached
+        // entries are reused and overwritten along the way
+        let weights = EvalWeights::default();
+        let mut pawn_table = PawnTable::default();
+        for fen in POSITIONS {
+            let mut board = Board::from_fen(fen).unwrap();
+            for mv in board.generate_legal_moves() {
+                let undo_state = board.make_move(mv);
+                for _ in 0..2 {
+                    assert_eq!(pawn_table.probe(&board), evaluate_pawns(&board));
+                    assert_eq!(evaluate(&board, &mut pawn_table, &weights), evaluate(&board, &mut PawnTable::default(), &weights));
+                }
+                board.unmake_move(mv, undo_state);
+            }
+        }
+    }
+
+    #[test]
+    fn trace_adds_up_to_the_evaluation() {
+        let weights = EvalWeights::default();
+        for fen in POSITIONS {
+            let board = Board::from_fen(fen).unwrap();
+            let trace = trace(&board, &weights);
+
+            let terms = |side: &SideTerms| side.material + side.piece_squares + side.pawns + side.mobility + side.king_safety;
+            assert_eq!(terms(&trace.white), trace.white.total());
+            assert_eq!(terms(&trace.black), trace.black.total());
+            assert_eq!((terms(&trace.white) - terms(&trace.black)).taper(trace.phase), trace.white_score());
+            assert_eq!(trace.phase, game_phase(&board));
+
+            assert_eq!(trace.score(), evaluate(&board, &mut PawnTable::default(), &weights), "{}", fen);
+            assert_eq!(trace.score(), if board.side_to_move == WHITE { trace.white_score() } else { -trace.white_score() });
+        }
+    }
+
+    #[test]
+    fn trace_prints_every_term() {
+        let text = trace(&Board::new(), &EvalWeights::default()).to_string();
+        for term in ["Material", "Piece squares", "Pawns", "Mobility", "King safety", "Total", "Phase: 24 of 24"] {
+            assert!(text.contains(term), "{}", text);
+        }
+        assert!(text.ends_with("Evaluation: 0 cp for White, 0 cp for the side to move (White)"), "{}", text);
+    }
+
+    fn white_term(fen: &str, term: fn(&Board, bool, &EvalWeights) -> TaperedScore) -> TaperedScore {
+        term(&Board::from_fen(fen).unwrap(), WHITE, &EvalWeights::default())
+    }
+
+    #[test]
+    fn squares_covered_by_enemy_pawns_are_not_mobility() {
+        let weights = EvalWeights::default();
+        // The knight on d4 reaches eight squares; the d7 pawn covers two of them, c6 and e6
+        let free = white_term("4k3/8/8/8/3N4/8/8/4K3 w - - 0 1", mobility);
+        let covered = white_term("4k3/3p4/8/8/3N4/8/8/4K3 w - - 0 1", mobility);
+        assert_eq!(free - covered, weights.mobility[0] * 2);
+
+        // Squares taken by its own pieces don't count either
+        let blocked = white_term("4k3/8/8/8/3N4/5P2/8/4K3 w - - 0 1", mobility);
+        assert_eq!(free - blocked, weights.mobility[0]);
+    }
+
+    #[test]
+    fn gaps_in_the_pawn_shield_lower_king_safety() {
+        let weights = EvalWeights::default();
+        let shield = white_term("6k1/5ppp/8/8/8/8/5PPP/6K1 w - - 0 1", king_safety);
+        let advanced = white_term("6k1/5ppp/8/8/6P1/8/5P1P/6K1 w - - 0 1", king_safety);
+        let semi_open = white_term("6k1/5ppp/8/8/8/8/5P1P/6K1 w - - 0 1", king_safety);
+        let open = white_term("6k1/5p1p/8/8/8/8/5P1P/6K1 w - - 0 1", king_safety);
+
+        assert_eq!(shield - advanced, weights.pawn_shield[0]);
+        assert_eq!(advanced - semi_open, -weights.semi_open_file);
+        assert_eq!(semi_open - open, weights.semi_open_file - weights.open_file);
+        assert!(shield.mg > advanced.mg && advanced.mg > semi_open.mg && semi_open.mg > open.mg);
+    }
+
+    #[test]
+    fn attackers_of_the_king_zone_lower_king_safety() {
+        // A lone queen hardly counts, a rook joining her does
+        let alone = white_term("6k1/8/8/8/7q/8/5PPP/6K1 w - - 0 1", king_safety);
+        let together = white_term("6k1/8/8/8/7q/8/5PPP/4r1K1 w - - 0 1", king_safety);
+        assert!(together.mg < alone.mg, "{:?} with a rook, {:?} without", together, alone);
+    }
+}
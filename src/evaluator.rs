@@ -0,0 +1,149 @@
+// evaluator.rs
+// The evaluation the search uses, behind a trait so other evaluations can be plugged into the
+// engine without touching the search. Two are provided: a plain material count, and the
+// classical evaluation of eval.rs with its pawn hash table.
+use std::fmt;
+
+use crate::board::{Board, BLACK, WHITE};
+use crate::eval::{self, EvalTrace, EvalWeights};
+use crate::pawns::PawnTable;
+use crate::piece::PieceType;
+use crate::r#move::Move;
+
+pub trait Evaluator: fmt::Debug + Send {
+    // Evaluation of the position in centipawns, from the point of view of the side to move.
+    // Checkmate and draws are left to the search.
+    fn evaluate(&mut self, board: &Board) -> i32;
+
+    // Called by the search right after it makes a move on `board`, and right after it takes one
+    // back, for evaluations that keep state up to date incrementally. Null moves are not reported,
+    // since they leave every piece where it is.
+    fn on_make_move(&mut self, _board: &Board, _mv: Move) {}
+    fn on_unmake_move(&mut self, _board: &Board, _mv: Move) {}
+
+    // Every search thread gets its own copy
+    fn box_clone(&self) -> Box<dyn Evaluator>;
+}
+
+impl Clone for Box<dyn Evaluator> {
+    fn clone(&self) -> Self {
+        self.box_clone()
+    }
+}
+
+// Counts material only, with the piece values used everywhere else in the engine
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MaterialEvaluator;
+
+impl Evaluator for MaterialEvaluator {
+    fn evaluate(&mut self, board: &Board) -> i32 {
+        let material = |side| {
+            [PieceType::Pawn, PieceType::Knight, PieceType::Bishop, PieceType::Rook, PieceType::Queen]
+                .iter()
+                .map(|&piece_type| board.pieces(piece_type, side).count_ones() as i32 * piece_type.value())
+                .sum::<i32>()
+        };
+
+        let balance = material(WHITE) - material(BLACK);
+        if board.side_to_move == WHITE { balance } else { -balance }
+    }
+
+    fn box_clone(&self) -> Box<dyn Evaluator> {
+        Box::new(*self)
+    }
+}
+
+// Tapered material and piece-square tables, pawn structure, mobility and king safety
+#[derive(Clone, Debug, Default)]
+pub struct ClassicalEvaluator {
+    weights: EvalWeights,
+    pawn_table: PawnTable,
+}
+
+impl ClassicalEvaluator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_weights(weights: EvalWeights) -> Self {
+        Self { weights, pawn_table: PawnTable::default() }
+    }
+
+    pub fn weights(&self) -> &EvalWeights {
+        &self.weights
+    }
+
+    // Every term of this evaluation of the board
+    pub fn trace(&self, board: &Board) -> EvalTrace {
+        eval::trace(board, &self.weights)
+    }
+}
+
+impl Evaluator for ClassicalEvaluator {
+    fn evaluate(&mut self, board: &Board) -> i32 {
+        eval::evaluate(board, &mut self.pawn_table, &self.weights)
+    }
+
+    fn box_clone(&self) -> Box<dyn Evaluator> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{Engine, SearchLimits};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    // Keeps the moves it was told about as a stack, which every take-back must pop in order
+    #[derive(Clone, Debug, Default)]
+    struct CountingEvaluator {
+        made: Arc<Mutex<Vec<Move>>>,
+        makes: Arc<AtomicUsize>,
+        unmakes: Arc<AtomicUsize>,
+    }
+
+    impl Evaluator for CountingEvaluator {
+        fn evaluate(&mut self, board: &Board) -> i32 {
+            MaterialEvaluator.evaluate(board)
+        }
+
+        fn on_make_move(&mut self, _board: &Board, mv: Move) {
+            self.made.lock().unwrap().push(mv);
+            self.makes.fetch_add(1, Ordering::Relaxed);
+        }
+
+        fn on_unmake_move(&mut self, _board: &Board, mv: Move) {
+            self.unmakes.fetch_add(1, Ordering::Relaxed);
+            assert_eq!(self.made.lock().unwrap().pop(), Some(mv), "took back a move that was not the last one made");
+        }
+
+        fn box_clone(&self) -> Box<dyn Evaluator> {
+            Box::new(self.clone())
+        }
+    }
+
+    #[test]
+    fn search_reports_every_move_and_take_back() {
+        let evaluator = CountingEvaluator::default();
+        let mut engine = Engine::with_board(Some(Board::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1").unwrap()));
+        engine.set_evaluator(Box::new(evaluator.clone()));
+        engine.search(&SearchLimits::depth(4), |_| {});
+
+        let makes = evaluator.makes.load(Ordering::Relaxed);
+        assert!(makes > 0);
+        assert_eq!(makes, evaluator.unmakes.load(Ordering::Relaxed));
+        assert!(evaluator.made.lock().unwrap().is_empty(), "moves made but never taken back");
+    }
+
+    #[test]
+    fn material_is_counted_for_the_side_to_move() {
+        // White is a knight up
+        let white_to_move = Board::from_fen("4k3/8/8/8/8/8/8/1N2K3 w - - 0 1").unwrap();
+        let black_to_move = Board::from_fen("4k3/8/8/8/8/8/8/1N2K3 b - - 0 1").unwrap();
+        assert_eq!(MaterialEvaluator.evaluate(&white_to_move), PieceType::Knight.value());
+        assert_eq!(MaterialEvaluator.evaluate(&black_to_move), -PieceType::Knight.value());
+        assert_eq!(MaterialEvaluator.evaluate(&Board::new()), 0);
+    }
+}
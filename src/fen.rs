@@ -0,0 +1,412 @@
+// fen.rs
+// Reading and writing positions in Forsyth-Edwards Notation
+use std::fmt;
+
+use crate::board::{rank_mask, Board, BLACK, WHITE};
+use crate::piece::PieceType;
+use crate::utils::{get_rank, parse_square, square_name};
+
+// Everything that can be wrong with a FEN string
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FenError {
+    FieldCount(usize),                        // Expected 6 fields, or 4 for EPD
+    RankCount(usize),                         // The piece placement must describe 8 ranks
+    BadRankLength { rank: u8, squares: u32 }, // A rank (1-8) that does not add up to 8 squares
+    UnknownPiece(char),
+    BadSideToMove(String),
+    BadCastling(String),
+    CastlingWithoutPieces(char),              // A castling right whose king or rook is not on its original square
+    BadEnPassant(String),
+    BadHalfmoveClock(String),
+    BadFullmoveNumber(String),
+    MissingKing(bool),                        // The given side has no king
+    TooManyKings(bool),                       // The given side has more than one king
+    PawnOnBackRank(u8),                       // Square of a pawn on the first or last rank
+    OpponentInCheck,                          // The side that just moved is in check
+}
+
+impl fmt::Display for FenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let side_name = |side: &bool| if *side == WHITE { "white" } else { "black" };
+        match self {
+            FenError::FieldCount(count) => write!(f, "expected 6 fields (or 4 for EPD), found {}", count),
+            FenError::RankCount(count) => write!(f, "expected 8 ranks, found {}", count),
+            FenError::BadRankLength { rank, squares } => write!(f, "rank {} describes {} squares instead of 8", rank, squares),
+            FenError::UnknownPiece(c) => write!(f, "unknown piece '{}'", c),
+            FenError::BadSideToMove(field) => write!(f, "side to move must be 'w' or 'b', found '{}'", field),
+            FenError::BadCastling(field) => write!(f, "invalid castling rights '{}'", field),
+            FenError::CastlingWithoutPieces(c) => write!(f, "castling right '{}' without king and rook on their original squares", c),
+            FenError::BadEnPassant(field) => write!(f, "invalid en passant square '{}'", field),
+            FenError::BadHalfmoveClock(field) => write!(f, "invalid halfmove clock '{}'", field),
+            FenError::BadFullmoveNumber(field) => write!(f, "invalid fullmove number '{}'", field),
+            FenError::MissingKing(side) => write!(f, "{} has no king", side_name(side)),
+            FenError::TooManyKings(side) => write!(f, "{} has more than one king", side_name(side)),
+            FenError::PawnOnBackRank(square) => write!(f, "pawn on {}", square_name(*square)),
+            FenError::OpponentInCheck => write!(f, "the side not to move is in check"),
+        }
+    }
+}
+
+impl std::error::Error for FenError {}
+
+impl Board {
+    // Parses a FEN string. EPD-style strings with only the first four fields are accepted too,
+    // with the move counters defaulting to 0 and 1.
+    pub fn from_fen(fen: &str) -> Result<Board, FenError> {
+        let fields: Vec<&str> = fen.split_whitespace().collect();
+        if fields.len() != 4 && fields.len() != 6 {
+            return Err(FenError::FieldCount(fields.len()));
+        }
+
+        let mut board = Board {
+            white_pawns: 0,
+            white_knights: 0,
+            white_bishops: 0,
+            white_rooks: 0,
+            white_queens: 0,
+            white_king: 0,
+            black_pawns: 0,
+            black_knights: 0,
+            black_bishops: 0,
+            black_rooks: 0,
+            black_queens: 0,
+            black_king: 0,
+            ..Board::new()
+        };
+
+        board.parse_pieces(fields[0])?;
+        board.side_to_move = match fields[1] {
+            "w" => WHITE,
+            "b" => BLACK,
+            other => return Err(FenError::BadSideToMove(other.to_string())),
+        };
+        board.parse_castling_rights(fields[2])?;
+        board.parse_en_passant(fields[3])?;
+
+        if fields.len() == 6 {
+            board.halfmove_clock = fields[4].parse().map_err(|_| FenError::BadHalfmoveClock(fields[4].to_string()))?;
+            board.fullmove_number = match fields[5].parse() {
+                Ok(number) if number > 0 => number,
+                _ => return Err(FenError::BadFullmoveNumber(fields[5].to_string())),
+            };
+        } else {
+            board.halfmove_clock = 0;
+            board.fullmove_number = 1;
+        }
+
+        board.validate()?;
+        board.hash = board.compute_hash();
+        board.pawn_key = board.compute_pawn_key();
+        Ok(board)
+    }
+
+    // Writes the position as a FEN string
+    pub fn to_fen(&self) -> String {
+        let mut placement = String::new();
+        for rank in (0..8).rev() {
+            let mut empty = 0;
+            for file in 0..8 {
+                match self.piece_at(rank * 8 + file) {
+                    Some((piece_type, side)) => {
+                        if empty > 0 {
+                            placement.push_str(&empty.to_string());
+                            empty = 0;
+                        }
+                        placement.push(piece_char(piece_type, side));
+                    },
+                    None => empty += 1,
+                }
+            }
+            if empty > 0 {
+                placement.push_str(&empty.to_string());
+            }
+            if rank > 0 {
+                placement.push('/');
+            }
+        }
+
+        let side_to_move = if self.side_to_move == WHITE { "w" } else { "b" };
+
+        let mut castling = String::new();
+        for (mask, c) in [(0b0001, 'K'), (0b0010, 'Q'), (0b0100, 'k'), (0b1000, 'q')] {
+            if self.castling_rights & mask != 0 {
+                castling.push(c);
+            }
+        }
+        if castling.is_empty() {
+            castling.push('-');
+        }
+
+        let en_passant = self.en_passant.map_or("-".to_string(), square_name);
+
+        format!("{} {} {} {} {} {}", placement, side_to_move, castling, en_passant, self.halfmove_clock, self.fullmove_number)
+    }
+
+    fn parse_pieces(&mut self, placement: &str) -> Result<(), FenError> {
+        let ranks: Vec<&str> = placement.split('/').collect();
+        if ranks.len() != 8 {
+            return Err(FenError::RankCount(ranks.len()));
+        }
+
+        // FEN lists the ranks from the 8th down to the 1st
+        for (index, rank_text) in ranks.iter().enumerate() {
+            let rank = 7 - index as u8;
+            let mut file: u32 = 0;
+
+            for c in rank_text.chars() {
+                if let Some(empty) = c.to_digit(10).filter(|empty| (1..=8).contains(empty)) {
+                    file += empty;
+                    continue;
+                }
+
+                if file >= 8 {
+                    return Err(FenError::BadRankLength { rank: rank + 1, squares: file + 1 });
+                }
+
+                let bitboard = 1u64 << (rank as u32 * 8 + file);
+                match c {
+                    'P' => self.white_pawns |= bitboard,
+                    'N' => self.white_knights |= bitboard,
+                    'B' => self.white_bishops |= bitboard,
+                    'R' => self.white_rooks |= bitboard,
+                    'Q' => self.white_queens |= bitboard,
+                    'K' => self.white_king |= bitboard,
+                    'p' => self.black_pawns |= bitboard,
+                    'n' => self.black_knights |= bitboard,
+                    'b' => self.black_bishops |= bitboard,
+                    'r' => self.black_rooks |= bitboard,
+                    'q' => self.black_queens |= bitboard,
+                    'k' => self.black_king |= bitboard,
+                    _ => return Err(FenError::UnknownPiece(c)),
+                }
+                file += 1;
+            }
+
+            if file != 8 {
+                return Err(FenError::BadRankLength { rank: rank + 1, squares: file });
+            }
+        }
+
+        Ok(())
+    }
+
+    fn parse_castling_rights(&mut self, field: &str) -> Result<(), FenError> {
+        self.castling_rights = 0;
+        if field == "-" {
+            return Ok(());
+        }
+
+        for c in field.chars() {
+            let mask = match c {
+                'K' => 0b0001,
+                'Q' => 0b0010,
+                'k' => 0b0100,
+                'q' => 0b1000,
+                _ => return Err(FenError::BadCastling(field.to_string())),
+            };
+            if self.castling_rights & mask != 0 {
+                return Err(FenError::BadCastling(field.to_string())); // Each right may only appear once
+            }
+            self.castling_rights |= mask;
+        }
+
+        Ok(())
+    }
+
+    fn parse_en_passant(&mut self, field: &str) -> Result<(), FenError> {
+        if field == "-" {
+            self.en_passant = None;
+            return Ok(());
+        }
+
+        let bad_square = || FenError::BadEnPassant(field.to_string());
+        let square = parse_square(field).ok_or_else(bad_square)?;
+
+        // The target square is behind a pawn that just moved two squares, so it is on
+        // the 6th rank when white is to move and on the 3rd when black is
+        let (target_rank, enemy_pawns) = if self.side_to_move == WHITE { (5, self.black_pawns) } else { (2, self.white_pawns) };
+        if get_rank(square) != target_rank {
+            return Err(bad_square());
+        }
+        let pawn_square = if self.side_to_move == WHITE { square - 8 } else { square + 8 };
+        if enemy_pawns & (1u64 << pawn_square) == 0 || self.occupancy() & (1u64 << square) != 0 {
+            return Err(bad_square());
+        }
+
+        // Many writers give the square after every double push. It is dropped when no pawn can
+        // capture on it, just as make_move does, so the position hashes the same either way.
+        self.en_passant = Some(square).filter(|&square| self.can_capture_en_passant(square, self.side_to_move));
+        Ok(())
+    }
+
+    // Rejects positions that parse but cannot be played from
+    fn validate(&self) -> Result<(), FenError> {
+        for (side, king) in [(WHITE, self.white_king), (BLACK, self.black_king)] {
+            match king.count_ones() {
+                0 => return Err(FenError::MissingKing(side)),
+                1 => {},
+                _ => return Err(FenError::TooManyKings(side)),
+            }
+        }
+
+        let pawns_on_back_rank = (self.white_pawns | self.black_pawns) & (rank_mask(0) | rank_mask(7));
+        if pawns_on_back_rank != 0 {
+            return Err(FenError::PawnOnBackRank(pawns_on_back_rank.trailing_zeros() as u8));
+        }
+
+        // Castling needs the king and the rook on their original squares
+        for (mask, c, king, rooks, king_square, rook_square) in [
+            (0b0001, 'K', self.white_king, self.white_rooks, 4, 7),
+            (0b0010, 'Q', self.white_king, self.white_rooks, 4, 0),
+            (0b0100, 'k', self.black_king, self.black_rooks, 60, 63),
+            (0b1000, 'q', self.black_king, self.black_rooks, 60, 56),
+        ] {
+            if self.castling_rights & mask != 0 && (king & (1u64 << king_square) == 0 || rooks & (1u64 << rook_square) == 0) {
+                return Err(FenError::CastlingWithoutPieces(c));
+            }
+        }
+
+        if self.is_in_check(!self.side_to_move) {
+            return Err(FenError::OpponentInCheck);
+        }
+
+        Ok(())
+    }
+}
+
+// FEN letter for a piece: upper case for white, lower case for black
+pub fn piece_char(piece_type: PieceType, side: bool) -> char {
+    let c = match piece_type {
+        PieceType::Pawn => 'p',
+        PieceType::Knight => 'n',
+        PieceType::Bishop => 'b',
+        PieceType::Rook => 'r',
+        PieceType::Queen => 'q',
+        PieceType::King => 'k',
+    };
+    if side == WHITE { c.to_ascii_uppercase() } else { c }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const START: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+    fn error(fen: &str) -> FenError {
+        Board::from_fen(fen).unwrap_err()
+    }
+
+    #[test]
+    fn round_trips() {
+        for fen in [
+            START,
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+            "rnbqkbnr/ppp1pppp/8/8/3pP3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 3",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/8/8/8/8/8/8/R3K2R b Kq - 37 80",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        ] {
+            let board = Board::from_fen(fen).unwrap();
+            assert_eq!(board.to_fen(), fen);
+            assert_eq!(board.hash, board.compute_hash());
+        }
+    }
+
+    #[test]
+    fn start_position_matches_new_board() {
+        let board = Board::from_fen(START).unwrap();
+        assert_eq!(board.to_fen(), Board::new().to_fen());
+        assert_eq!(board.hash, Board::new().hash);
+    }
+
+    #[test]
+    fn epd_defaults_move_counters() {
+        let board = Board::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq -").unwrap();
+        assert_eq!(board.to_fen(), START);
+    }
+
+    #[test]
+    fn rejects_wrong_field_count() {
+        assert_eq!(error("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0"), FenError::FieldCount(5));
+    }
+
+    #[test]
+    fn rejects_wrong_rank_count() {
+        assert_eq!(error("rnbqkbnr/pppppppp/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"), FenError::RankCount(7));
+    }
+
+    #[test]
+    fn rejects_bad_rank_length() {
+        assert_eq!(error("rnbqkbnr/pppppppp/8/8/7/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"), FenError::BadRankLength { rank: 4, squares: 7 });
+        assert_eq!(error("rnbqkbnr/pppppppp/8/8/44P/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"), FenError::BadRankLength { rank: 4, squares: 9 });
+    }
+
+    #[test]
+    fn rejects_unknown_piece() {
+        assert_eq!(error("rnbqkbnr/pppppppp/8/8/3X4/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"), FenError::UnknownPiece('X'));
+    }
+
+    #[test]
+    fn rejects_bad_side_to_move() {
+        assert_eq!(error("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR x KQkq - 0 1"), FenError::BadSideToMove("x".to_string()));
+    }
+
+    #[test]
+    fn rejects_bad_castling() {
+        assert_eq!(error("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkx - 0 1"), FenError::BadCastling("KQkx".to_string()));
+        assert_eq!(error("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KKq - 0 1"), FenError::BadCastling("KKq".to_string()));
+    }
+
+    #[test]
+    fn rejects_castling_without_pieces() {
+        assert_eq!(error("rnbqkbn1/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"), FenError::CastlingWithoutPieces('k'));
+    }
+
+    #[test]
+    fn rejects_bad_en_passant() {
+        assert_eq!(error("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq e9 0 1"), FenError::BadEnPassant("e9".to_string()));
+        // No pawn just moved past the square
+        assert_eq!(error("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq e6 0 1"), FenError::BadEnPassant("e6".to_string()));
+    }
+
+    #[test]
+    fn drops_en_passant_no_pawn_can_take() {
+        let board = Board::from_fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1").unwrap();
+        assert_eq!(board.en_passant, None);
+        assert_eq!(board.to_fen(), "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1");
+        assert_eq!(board.hash, board.compute_hash());
+    }
+
+    #[test]
+    fn rejects_bad_halfmove_clock() {
+        assert_eq!(error("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - x 1"), FenError::BadHalfmoveClock("x".to_string()));
+    }
+
+    #[test]
+    fn rejects_bad_fullmove_number() {
+        assert_eq!(error("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 0"), FenError::BadFullmoveNumber("0".to_string()));
+    }
+
+    #[test]
+    fn rejects_missing_king() {
+        assert_eq!(error("rnbq1bnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQ - 0 1"), FenError::MissingKing(BLACK));
+    }
+
+    #[test]
+    fn rejects_too_many_kings() {
+        assert_eq!(error("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBKKBNR w kq - 0 1"), FenError::TooManyKings(WHITE));
+    }
+
+    #[test]
+    fn rejects_pawn_on_back_rank() {
+        assert_eq!(error("rnbqkbnP/pppppppp/8/8/8/8/PPPPPPP1/RNBQKBNR w KQq - 0 1"), FenError::PawnOnBackRank(63));
+    }
+
+    #[test]
+    fn rejects_opponent_in_check() {
+        assert_eq!(error("4k3/8/8/8/8/8/8/r3K3 b - - 0 1"), FenError::OpponentInCheck);
+        assert_eq!(error("4k3/8/8/8/8/8/8/4R1K1 w - - 0 1"), FenError::OpponentInCheck);
+    }
+}
@@ -1,17 +1,27 @@
 // game.rs
 
-use crate::board::{ Board, UndoState, WHITE, BLACK };
+use crate::board::{ Board, UndoState, WHITE };
 use crate::r#move::Move;
 use crate::piece::PieceType;
+use crate::zobrist;
+use crate::fen::FenError;
+use crate::pgn::write_pgn;
 
 use std::cmp;
+use std::fmt;
 
 
+impl Default for Board {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Board {
 
     // Initializes the board to the starting position
     pub fn new() -> Self {
-        Board {
+        let mut board = Board {
             white_pawns: 0xFF00, // 2nd rank
             white_knights: 0x42, // b1 and g1
             white_bishops: 0x24, // c1 and f1
@@ -31,10 +41,16 @@ impl Board {
             side_to_move: WHITE,
             halfmove_clock: 0,
             fullmove_number: 1,
+            hash: 0,
+            pawn_key: 0,
+            history: Vec::new(),
             is_checkmate: false,
             is_draw: false,
-        }
+        };
 
+        board.hash = board.compute_hash();
+        board.pawn_key = board.compute_pawn_key();
+        board
     }
 
     pub fn make_move(&mut self, mv: Move) -> UndoState {
@@ -57,18 +73,24 @@ impl Board {
 
         //save board state so we can undo it later. combined with Move, can fully undo move.
         let undo_state = UndoState {
-            //TODO: Verify unmake move and undo state work appropriately with zobrist hashes
-            captured_piece: captured_piece,
+            captured_piece,
             en_passant: self.en_passant,
             castling_rights: self.castling_rights,
             halfmove_clock: self.halfmove_clock,
             fullmove_number: self.fullmove_number,
+            hash: self.hash,
             is_checkmate: self.is_checkmate,
             is_draw: self.is_draw,
         };
 
-        // let from_mask = 1u64 << mv.from;
-        let to_mask = 1u64 << mv.to;
+        self.history.push(self.hash);
+
+        // Take the old castling rights and en passant square out of the hash, the new ones are added at the end.
+        // Piece keys are updated by clear_square and set_square as the pieces move.
+        self.hash ^= zobrist::CASTLING_KEYS[self.castling_rights as usize];
+        if let Some(en_passant) = self.en_passant {
+            self.hash ^= zobrist::en_passant_key(en_passant);
+        }
 
         // Handle the halfmove clock with ternary operator
         let next_halfmove = cmp::min(100, self.halfmove_clock + 1); //todo: remove this line
@@ -83,56 +105,7 @@ impl Board {
         self.clear_square(mv.from);
 
         // Set the 'to' square for the appropriate piece
-        match mv.piece_type {
-            PieceType::Pawn => {
-                // self.halfmove_clock = 0; // Reset the halfmove clock
-                if self.side_to_move == WHITE {
-                    self.white_pawns |= to_mask;
-                } else {
-                    self.black_pawns |= to_mask;
-                }
-            },
-
-            PieceType::Knight => {
-                if self.side_to_move == WHITE {
-                    self.white_knights |= to_mask;
-                } else {
-                    self.black_knights |= to_mask;
-                }
-            },
-
-            PieceType::Rook => {
-                if self.side_to_move == WHITE {
-                    self.white_rooks |= to_mask;
-                } else {
-                    self.black_rooks |= to_mask;
-                }
-            },
-
-            PieceType::Bishop => {
-                if self.side_to_move == WHITE {
-                    self.white_bishops |= to_mask;
-                } else {
-                    self.black_bishops |= to_mask;
-                }
-            },
-
-            PieceType::Queen => {
-                if self.side_to_move == WHITE {
-                    self.white_queens |= to_mask;
-                } else {
-                    self.black_queens |= to_mask;
-                }
-            },
-
-            PieceType::King => {
-                if self.side_to_move == WHITE {
-                    self.white_king |= to_mask;
-                } else {
-                    self.black_king |= to_mask;
-                }
-            },
-        }
+        self.set_square(mv.to, mv.piece_type);
 
         // Handle castling move
         if mv.piece_type == PieceType::King && mv.from.abs_diff(mv.to) == 2 {
@@ -142,12 +115,11 @@ impl Board {
         // Handle castling rights
         self.update_castling_rights(&mv);
 
-        // Handle en passant
-        if let Some(pawn_move) = self.handle_pawn_move(&mv) {
-            self.en_passant = pawn_move.en_passant;
-        } else {
-            self.en_passant = None;
-        }
+        // Handle en passant. The square is only kept when an enemy pawn can actually capture on it.
+        self.en_passant = self
+            .handle_pawn_move(&mv)
+            .and_then(|pawn_move| pawn_move.en_passant)
+            .filter(|&square| self.can_capture_en_passant(square, !self.side_to_move));
 
         // Handle potential promotion
         if let Some(promotion) = mv.promotion {
@@ -156,11 +128,20 @@ impl Board {
 
         // Toggle the side to move
         self.side_to_move = !self.side_to_move;
+        self.hash ^= zobrist::SIDE_KEY;
+
+        self.hash ^= zobrist::CASTLING_KEYS[self.castling_rights as usize];
+        if let Some(en_passant) = self.en_passant {
+            self.hash ^= zobrist::en_passant_key(en_passant);
+        }
 
         // Update the fullmove number if Black has moved
         if self.side_to_move == WHITE {
             self.fullmove_number += 1;
         }
+
+        debug_assert_eq!(self.hash, self.compute_hash(), "incremental hash diverged after {}", mv.get_uci());
+        debug_assert_eq!(self.pawn_key, self.compute_pawn_key(), "incremental pawn key diverged after {}", mv.get_uci());
         
         // Return the undo state
         undo_state
@@ -173,12 +154,11 @@ impl Board {
         self.clear_square(mv.to);
 
         // Restore the captured piece, if any
-        if Some(mv.to) == undo_state.en_passant {
+        if mv.piece_type == PieceType::Pawn && Some(mv.to) == undo_state.en_passant {
             let captured_square = if self.side_to_move == WHITE { mv.to + 8 } else { mv.to - 8 };
             self.set_square(captured_square, PieceType::Pawn);
         } else if let Some(captured_piece) = undo_state.captured_piece {
             self.set_square(mv.to, captured_piece);
-        } else {
         }
 
         // Toggle the side to move back
@@ -208,104 +188,439 @@ impl Board {
             self.clear_square(rook_to); // TODO: this is probably unnecessary, since castling needs clear space beforehand
             self.set_square(rook_from, PieceType::Rook);
         }
+
+        // The squares above moved the piece keys back; the rest of the hash is simply restored
+        self.hash = undo_state.hash;
+        self.history.pop();
+        debug_assert_eq!(self.hash, self.compute_hash(), "incremental hash diverged undoing {}", mv.get_uci());
+        debug_assert_eq!(self.pawn_key, self.compute_pawn_key(), "incremental pawn key diverged undoing {}", mv.get_uci());
     }
 
-    pub fn set_pos(&mut self, fen: &str) {
-        let parts: Vec<&str> = fen.split_whitespace().collect();
-        if parts.len() != 6 {
-            panic!("Invalid FEN string");
-        }
+    // Passes the turn, for null-move pruning. This is not a legal chess move: only the side to move
+    // and the en passant square change. The halfmove clock restarts, so no repetition is found
+    // across the pass, since the positions before it weren't reached legally.
+    pub fn make_null_move(&mut self) -> UndoState {
+        let undo_state = UndoState {
+            captured_piece: None,
+            en_passant: self.en_passant,
+            castling_rights: self.castling_rights,
+            halfmove_clock: self.halfmove_clock,
+            fullmove_number: self.fullmove_number,
+            hash: self.hash,
+            is_checkmate: self.is_checkmate,
+            is_draw: self.is_draw,
+        };
+
+        self.history.push(self.hash);
 
-        self.reset_board(); // Clear the board or reset it to default state
-
-        self.set_pieces(parts[0]); // Parts[0] contains piece placement
-        self.side_to_move = if parts[1] == "w" { WHITE } else { BLACK };
-        self.set_castling_rights(parts[2]);
-        self.set_en_passant(parts[3]);
-        self.halfmove_clock = parts[4].parse().unwrap_or(0);
-        self.fullmove_number = parts[5].parse().unwrap_or(1);
-    }
-
-    fn reset_board(&mut self) {
-        // Reset the board to the default starting position
-        self.white_pawns = 0;
-        self.white_knights = 0;
-        self.white_bishops = 0;
-        self.white_rooks = 0;
-        self.white_queens = 0;
-        self.white_king = 0;
-        self.black_pawns = 0;
-        self.black_knights = 0;
-        self.black_bishops = 0;
-        self.black_rooks = 0;
-        self.black_queens = 0;
-        self.black_king = 0;
-        self.side_to_move = WHITE;
-        self.castling_rights = 0b1111;
+        if let Some(en_passant) = self.en_passant {
+            self.hash ^= zobrist::en_passant_key(en_passant);
+        }
         self.en_passant = None;
         self.halfmove_clock = 0;
-        self.fullmove_number = 1;
+
+        self.side_to_move = !self.side_to_move;
+        self.hash ^= zobrist::SIDE_KEY;
+
+        debug_assert_eq!(self.hash, self.compute_hash(), "incremental hash diverged after a null move");
+
+        undo_state
     }
 
-    fn set_pieces(&mut self, pieces: &str) {
-        // Parse piece placement from the FEN and set the board
+    pub fn unmake_null_move(&mut self, undo_state: UndoState) {
+        self.side_to_move = !self.side_to_move;
+        self.en_passant = undo_state.en_passant;
+        self.halfmove_clock = undo_state.halfmove_clock;
+        self.hash = undo_state.hash;
+        self.history.pop();
+    }
+
+    // Replaces the position with the one described by a FEN string, leaving the board untouched on error
+    pub fn set_pos(&mut self, fen: &str) -> Result<(), FenError> {
+        *self = Board::from_fen(fen)?;
+        Ok(())
+    }
+}
+
+// How a game ended
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Termination {
+    Checkmate,
+    Stalemate,
+    FiftyMove,
+    Threefold,
+    InsufficientMaterial,
+    Resignation,
+    Timeout,
+    Agreement,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GameResult {
+    WhiteWins(Termination),
+    BlackWins(Termination),
+    Draw(Termination),
+}
+
+impl GameResult {
+    // A win for the given side
+    pub fn win(side: bool, termination: Termination) -> Self {
+        if side == WHITE { GameResult::WhiteWins(termination) } else { GameResult::BlackWins(termination) }
+    }
+
+    pub fn termination(&self) -> Termination {
+        match *self {
+            GameResult::WhiteWins(termination) | GameResult::BlackWins(termination) | GameResult::Draw(termination) => termination,
+        }
+    }
+
+    // The result as written in PGN
+    pub fn pgn_token(&self) -> &'static str {
+        match self {
+            GameResult::WhiteWins(_) => "1-0",
+            GameResult::BlackWins(_) => "0-1",
+            GameResult::Draw(_) => "1/2-1/2",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum GameError {
+    IllegalMove(Move),
+    GameOver(GameResult), // No more moves can be played once the game has a result
+    NoSuchPly(usize),     // jump_to was asked for a ply past the end of the game
+}
+
+impl fmt::Display for GameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GameError::IllegalMove(mv) => write!(f, "{} is not a legal move", mv.get_uci()),
+            GameError::GameOver(result) => write!(f, "the game is over ({} by {:?})", result.pgn_token(), result.termination()),
+            GameError::NoSuchPly(ply) => write!(f, "the game has no ply {}", ply),
+        }
+    }
+}
+
+impl std::error::Error for GameError {}
 
-        // Iterate over each rank
-        let mut rank = 7;
-        let mut file = 0;
-        for c in pieces.chars() {
-            if c == '/' {
-                rank -= 1;
-                file = 0;
-            } else if c.is_digit(10) {
-                file += c.to_digit(10).unwrap();
+impl Board {
+    // The result of the game if it is over by the rules, with nobody having to claim or agree anything
+    pub fn game_result(&mut self) -> Option<GameResult> {
+        if self.generate_legal_moves().is_empty() {
+            return Some(if self.is_in_check(self.side_to_move) {
+                GameResult::win(!self.side_to_move, Termination::Checkmate)
             } else {
-                let square = rank * 8 + file;
-                let bitboard = 1u64 << square;
-                match c {
-                    'P' => self.white_pawns |= bitboard,
-                    'N' => self.white_knights |= bitboard,
-                    'B' => self.white_bishops |= bitboard,
-                    'R' => self.white_rooks |= bitboard,
-                    'Q' => self.white_queens |= bitboard,
-                    'K' => self.white_king |= bitboard,
-                    'p' => self.black_pawns |= bitboard,
-                    'n' => self.black_knights |= bitboard,
-                    'b' => self.black_bishops |= bitboard,
-                    'r' => self.black_rooks |= bitboard,
-                    'q' => self.black_queens |= bitboard,
-                    'k' => self.black_king |= bitboard,
-                    _ => panic!("Invalid FEN string"),
-                }
-                file += 1;
-            }
+                GameResult::Draw(Termination::Stalemate)
+            });
+        }
+
+        if self.halfmove_clock >= 100 {
+            return Some(GameResult::Draw(Termination::FiftyMove));
+        }
+
+        if self.is_threefold_repetition() {
+            return Some(GameResult::Draw(Termination::Threefold));
+        }
+
+        if self.is_insufficient_material() {
+            return Some(GameResult::Draw(Termination::InsufficientMaterial));
         }
 
+        None
+    }
+}
+
+// A game from some starting position: the moves played so far, which can be taken back and
+// replayed, and the result once there is one
+#[derive(Clone, Debug)]
+pub struct Game {
+    start: Board,
+    board: Board,
+    moves: Vec<Move>,             // Every move of the game, including ones taken back that can be redone
+    undo_states: Vec<UndoState>,  // One for each move currently on the board, so its length is the current ply
+    result: Option<GameResult>,
+    declared: Option<(usize, GameResult)>, // A resignation, timeout or agreed draw, and the ply it was declared at
+}
+
+impl Default for Game {
+    fn default() -> Self {
+        Self::new()
     }
+}
 
-    fn set_castling_rights(&mut self, rights: &str) {
-        // Set the castling rights from the FEN
-        self.castling_rights = 0;
-        for c in rights.chars() {
-            match c {
-                'K' => self.castling_rights |= 0b0001,
-                'Q' => self.castling_rights |= 0b0010,
-                'k' => self.castling_rights |= 0b0100,
-                'q' => self.castling_rights |= 0b1000,
-                '-' => break,
-                _ => panic!("Invalid FEN string"),
-            }
+impl Game {
+    // A new game from the starting position
+    pub fn new() -> Self {
+        Self::from_board(Board::new())
+    }
+
+    // A new game from any position
+    pub fn from_board(start: Board) -> Self {
+        let mut board = start.clone();
+        let result = board.game_result();
+        Self {
+            start,
+            board,
+            moves: Vec::new(),
+            undo_states: Vec::new(),
+            result,
+            declared: None,
         }
     }
 
-    fn set_en_passant(&mut self, square: &str) {
-        // Set the en passant target square from the FEN
-        if square == "-" {
-            self.en_passant = None;
+    pub fn start(&self) -> &Board {
+        &self.start
+    }
+
+    // The current position
+    pub fn board(&self) -> &Board {
+        &self.board
+    }
+
+    // The moves that lead from the start to the current position
+    pub fn moves(&self) -> &[Move] {
+        &self.moves[..self.ply()]
+    }
+
+    // How many half-moves have been played to reach the current position
+    pub fn ply(&self) -> usize {
+        self.undo_states.len()
+    }
+
+    pub fn result(&self) -> Option<GameResult> {
+        self.result
+    }
+
+    // Plays a legal move. Any moves that were taken back are forgotten, so they can no longer be redone.
+    pub fn play(&mut self, mv: Move) -> Result<(), GameError> {
+        if let Some(result) = self.result {
+            return Err(GameError::GameOver(result));
+        }
+        if !self.board.generate_legal_moves().contains(&mv) {
+            return Err(GameError::IllegalMove(mv));
+        }
+
+        self.moves.truncate(self.ply());
+        self.declared = self.declared.filter(|&(ply, _)| ply <= self.ply());
+        self.moves.push(mv);
+        self.apply(mv);
+        Ok(())
+    }
+
+    // Takes back the last move, returning it. A resignation, timeout or agreed draw after that move
+    // no longer holds, but it comes back when the game returns to the ply it was declared at.
+    pub fn undo(&mut self) -> Option<Move> {
+        let undo_state = self.undo_states.pop()?;
+        let mv = self.moves[self.ply()];
+        self.board.unmake_move(mv, undo_state);
+        self.update_result();
+        Some(mv)
+    }
+
+    // Plays the last move taken back again, returning it
+    pub fn redo(&mut self) -> Option<Move> {
+        let mv = *self.moves.get(self.ply())?;
+        self.apply(mv);
+        Some(mv)
+    }
+
+    // Takes back or replays moves until the game is at the given ply
+    pub fn jump_to(&mut self, ply: usize) -> Result<(), GameError> {
+        if ply > self.moves.len() {
+            return Err(GameError::NoSuchPly(ply));
+        }
+
+        while self.ply() > ply {
+            self.undo();
+        }
+        while self.ply() < ply {
+            self.redo();
+        }
+        Ok(())
+    }
+
+    // The given side gives up
+    pub fn resign(&mut self, side: bool) {
+        self.declare(GameResult::win(!side, Termination::Resignation));
+    }
+
+    // The given side ran out of time. It is still a draw if the opponent has nothing left but the king.
+    pub fn timeout(&mut self, side: bool) {
+        let opponent_king = if side == WHITE { self.board.black_king } else { self.board.white_king };
+        let result = if self.board.side_occupancy(!side) == opponent_king {
+            GameResult::Draw(Termination::Timeout)
         } else {
-            let file = square.chars().nth(0).unwrap() as i8 - 'a' as i8;
-            let rank = square.chars().nth(1).unwrap() as i8 - '1' as i8;
-            self.en_passant = Some((rank * 8 + file) as u8);
+            GameResult::win(!side, Termination::Timeout)
+        };
+        self.declare(result);
+    }
+
+    // Both players agree to a draw
+    pub fn agree_draw(&mut self) {
+        self.declare(GameResult::Draw(Termination::Agreement));
+    }
+
+    // The game as PGN, with the given tags and a Result tag from the game's result
+    pub fn to_pgn(&self, tags: &[(String, String)]) -> String {
+        let mut tags = tags.to_vec();
+        tags.retain(|(name, _)| name != "Result");
+        if let Some(result) = self.result {
+            tags.push(("Result".to_string(), result.pgn_token().to_string()));
+        }
+        write_pgn(&tags, &self.start, self.moves(), &[])
+    }
+
+    fn apply(&mut self, mv: Move) {
+        let undo_state = self.board.make_move(mv);
+        self.undo_states.push(undo_state);
+        self.update_result();
+    }
+
+    // Ends the game with a result that does not follow from the position. A game that is already
+    // over keeps its result.
+    fn declare(&mut self, result: GameResult) {
+        if self.result.is_none() {
+            self.result = Some(result);
+            self.declared = Some((self.ply(), result));
         }
     }
-}
\ No newline at end of file
+
+    // The result at the current ply: the one the position decides, else one declared at this ply
+    fn update_result(&mut self) {
+        let ply = self.ply();
+        let declared = self.declared.filter(|&(declared_ply, _)| declared_ply == ply).map(|(_, result)| result);
+        self.result = self.board.game_result().or(declared);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::BLACK;
+
+    fn san(game: &Game, san: &str) -> Move {
+        game.board().clone().parse_san(san).unwrap()
+    }
+
+    fn play(game: &mut Game, sans: &str) {
+        for san_move in sans.split_whitespace() {
+            let mv = san(game, san_move);
+            game.play(mv).unwrap();
+        }
+    }
+
+    fn game_from(fen: &str) -> Game {
+        Game::from_board(Board::from_fen(fen).unwrap())
+    }
+
+    #[test]
+    fn refuses_illegal_moves() {
+        let mut game = Game::new();
+        let mv = Move::new(12, 36, PieceType::Pawn, None); // e2e5
+        assert_eq!(game.play(mv), Err(GameError::IllegalMove(mv)));
+        assert_eq!(game.ply(), 0);
+    }
+
+    #[test]
+    fn refuses_moves_once_the_game_is_over() {
+        let mut game = Game::new();
+        game.resign(WHITE);
+        let mv = san(&game, "e4");
+        assert_eq!(game.play(mv), Err(GameError::GameOver(GameResult::BlackWins(Termination::Resignation))));
+        assert_eq!(game.ply(), 0);
+    }
+
+    #[test]
+    fn undo_and_redo_round_trip() {
+        let mut game = Game::new();
+        play(&mut game, "e4 e5 Nf3");
+        let moves = game.moves().to_vec();
+        let fen = game.board().to_fen();
+
+        assert_eq!(game.undo(), Some(moves[2]));
+        assert_eq!(game.redo(), Some(moves[2]));
+        assert_eq!(game.redo(), None);
+        assert_eq!(game.board().to_fen(), fen);
+
+        while game.undo().is_some() {}
+        assert_eq!(game.board().to_fen(), Board::new().to_fen());
+        assert_eq!(game.board().hash, Board::new().hash);
+
+        while game.redo().is_some() {}
+        assert_eq!(game.moves(), &moves[..]);
+        assert_eq!(game.board().to_fen(), fen);
+    }
+
+    #[test]
+    fn playing_after_undo_forgets_the_moves_taken_back() {
+        let mut game = Game::new();
+        play(&mut game, "e4 e5 Nf3");
+        game.undo();
+        game.undo();
+        play(&mut game, "c5");
+
+        assert_eq!(game.ply(), 2);
+        assert_eq!(game.moves().len(), 2);
+        assert_eq!(game.redo(), None);
+    }
+
+    #[test]
+    fn jumps_to_plies_of_the_game() {
+        let mut game = Game::new();
+        play(&mut game, "d4 d5 c4");
+        let fen = game.board().to_fen();
+
+        game.jump_to(1).unwrap();
+        assert_eq!(game.ply(), 1);
+        game.jump_to(3).unwrap();
+        assert_eq!(game.board().to_fen(), fen);
+        assert_eq!(game.jump_to(4), Err(GameError::NoSuchPly(4)));
+        assert_eq!(game.ply(), 3);
+    }
+
+    #[test]
+    fn checkmate_and_stalemate_end_the_game() {
+        let mut game = Game::new();
+        play(&mut game, "f3 e5 g4 Qh4");
+        assert_eq!(game.result(), Some(GameResult::BlackWins(Termination::Checkmate)));
+        game.undo();
+        assert_eq!(game.result(), None);
+
+        let mut game = game_from("k7/8/8/2Q5/8/8/8/K7 w - - 0 1");
+        play(&mut game, "Qb6");
+        assert_eq!(game.result(), Some(GameResult::Draw(Termination::Stalemate)));
+    }
+
+    #[test]
+    fn timeout_against_a_lone_king_is_a_draw() {
+        let mut game = game_from("4k3/8/8/8/8/8/8/3QK3 w - - 0 1");
+        game.timeout(WHITE);
+        assert_eq!(game.result(), Some(GameResult::Draw(Termination::Timeout)));
+
+        let mut game = game_from("4k3/8/8/8/8/8/8/3QK3 w - - 0 1");
+        game.timeout(BLACK);
+        assert_eq!(game.result(), Some(GameResult::WhiteWins(Termination::Timeout)));
+    }
+
+    #[test]
+    fn declared_results_come_back_with_their_ply() {
+        let mut game = Game::new();
+        play(&mut game, "e4");
+        game.resign(BLACK);
+        let resigned = Some(GameResult::WhiteWins(Termination::Resignation));
+
+        game.undo();
+        assert_eq!(game.result(), None);
+        game.redo();
+        assert_eq!(game.result(), resigned);
+
+        game.jump_to(0).unwrap();
+        game.jump_to(1).unwrap();
+        assert_eq!(game.result(), resigned);
+
+        // A different move replaces the line the resignation was made in
+        game.undo();
+        play(&mut game, "d4");
+        assert_eq!(game.result(), None);
+    }
+}
@@ -4,4 +4,19 @@ pub mod piece;
 pub mod game;
 pub mod utils;
 pub mod r#move; // 'move' is a reserved keyword, so we use raw identifier syntaxgit remote add origin https://github.com/cmwetherell/rusty-engine.git
-pub mod engine;
\ No newline at end of file
+pub mod engine;
+pub mod zobrist;
+pub mod tt;
+pub mod attacks;
+pub mod uci;
+pub mod fen;
+pub mod san;
+pub mod pgn;
+pub mod score;
+pub mod movepick;
+pub mod see;
+pub mod time;
+pub mod search;
+pub mod eval;
+pub mod pawns;
+pub mod evaluator;
@@ -1,20 +1,39 @@
+use std::env;
+use std::fs;
 use std::io;
 use rusty_engine::board::{Board, Move, PieceType};
 use rusty_engine::engine::Engine;
+use rusty_engine::evaluator::ClassicalEvaluator;
+use rusty_engine::game::Game;
 use rusty_engine::r#move::ScoredMove;
+use rusty_engine::uci::Uci;
 
 fn main() {
+    // `rusty_engine uci` goes straight into UCI mode without printing the board
+    if env::args().nth(1).as_deref() == Some("uci") {
+        Uci::new().run();
+        return;
+    }
+
     let mut board = Board::new();
     // board.set_pos("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1"); // test from: https://www.chessprogramming.org/Perft_Results
     // board.set_pos("r3k2r/p1ppqpb1/bn2pnp1/3PN3/Pp2P3/2N2Q1p/1PPBBPPP/R3K2R b KQkq a3 0 1"); // test for en passant
     // board.set_pos("rnbqkbnr/p5pp/8/1ppppp2/3PP3/2N1BQ2/PPP2PPP/R3KBNR w KQkq - 0 6");
     // board.set_pos("rnbqkbnr/p5pp/8/1ppppp2/3PP3/2N1BQ2/PPP2PPP/RB2K2R w KQkq - 0 6");
     // board.set_pos("r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1");
-    board.set_pos("k7/3Q4/1K6/8/8/8/8/8 b - - 0 1"); // mate in 1
+    board.set_pos("k7/3Q4/1K6/8/8/8/8/8 b - - 0 1").expect("valid FEN"); // mate in 1
     // board.set_pos("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1");
+
+    // Keeps every move played, so the game can be saved as PGN
+    let mut game = Game::from_board(board);
     loop {
+        let mut board = game.board().clone();
         board.print_board();
 
+        if let Some(result) = game.result() {
+            println!("Game over: {} ({:?})", result.pgn_token(), result.termination());
+        }
+
         let valid_moves = board.generate_legal_moves();
         // create new list of reverese parse moves
         let mut reverse_parse_moves = Vec::new();
@@ -29,7 +48,7 @@ fn main() {
         
         // print total length of valid moves
         println!("Total valid moves: {}", valid_moves.len());
-        println!("Enter your move (e.g., e2e4), 'perft [depth]', or 'quit' to exit:");
+        println!("Enter your move (e.g., e2e4), 'perft [depth]', 'eval', 'pgn [file]', or 'quit' to exit:");
         
         let mut input = String::new();
         io::stdin().read_line(&mut input).unwrap();
@@ -37,6 +56,12 @@ fn main() {
 
         if trimmed_input == "quit" {
             break;
+        } else if trimmed_input == "uci" {
+            // A GUI is talking to us, hand stdin over to the UCI loop
+            let mut uci = Uci::new();
+            uci.handle_command(trimmed_input);
+            uci.run();
+            break;
         } else if trimmed_input.starts_with("perft") {
             if let Some(depth_str) = trimmed_input.split_whitespace().nth(1) {
                 if let Ok(depth) = depth_str.parse::<usize>() {
@@ -53,12 +78,34 @@ fn main() {
             //create new engine with current board
             let depth: usize = trimmed_input.split_whitespace().nth(1).unwrap().parse().unwrap();
             let mut engine: Engine = Engine::with_board(Some(board.clone()));
-            let search: Vec<ScoredMove> = engine.search_moves(1, depth);
+            let search: Vec<ScoredMove> = engine.search_moves(1, depth, |depth, best_moves| {
+                println!("Depth: {}", depth);
+                for scored_move in best_moves {
+                    let pv: Vec<String> = scored_move.pv.iter().map(|mv| mv.get_uci()).collect();
+                    println!("  {} ({}) pv {}", scored_move.mv.get_uci(), scored_move.score, pv.join(" "));
+                }
+            });
             println!("Search: {:?}", search);
+        } else if trimmed_input == "eval" {
+            // Break the evaluation of the position down into its terms
+            println!("{}", ClassicalEvaluator::new().trace(&board));
+        } else if trimmed_input.starts_with("pgn") {
+            // Print the game so far, or write it to the given file
+            let pgn = game.to_pgn(&[]);
+            match trimmed_input.split_whitespace().nth(1) {
+                Some(path) => match fs::write(path, &pgn) {
+                    Ok(()) => println!("Game saved to {}", path),
+                    Err(err) => println!("Could not write {}: {}", path, err),
+                },
+                None => println!("{}", pgn),
+            }
         } else if let Some(mv) = parse_move(trimmed_input, board.clone()) {
             // Check if move is in valid moves, else print error
             if valid_moves.contains(&mv) {
-                board.make_move(mv);
+                // Still refused once the game is over, e.g. by a threefold repetition
+                if let Err(err) = game.play(mv) {
+                    println!("Cannot play {}: {}", mv.get_uci(), err);
+                }
             } else {
                 println!("Invalid move");
             }
@@ -71,7 +118,7 @@ fn main() {
 
 fn parse_move(input: &str, board: Board) -> Option<Move> {
     if input.len() == 4 {
-        let from_file = input.chars().nth(0)?;
+        let from_file = input.chars().next()?;
         let from_rank = input.chars().nth(1)?.to_digit(10)? as u8; // Cast to u8 here to match types for addition
         let to_file = input.chars().nth(2)?;
         let to_rank = input.chars().nth(3)?.to_digit(10)? as u8; // Cast to u8 here as well
@@ -1,5 +1,6 @@
 // move.rs
 use crate::piece::PieceType;
+use crate::score::Score;
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Move {
@@ -31,28 +32,36 @@ impl Move {
         self.to
     }
 
-    // Get UCI notation, e.g. e2e4
+    // Get UCI notation, e.g. e2e4 or e7e8q
     pub fn get_uci(&self) -> String {
-        let from_file = (self.from % 8) as u8 + b'a'; // converting file to a-h
-        let from_rank = (self.from / 8) as u8 + 1;    // rank as 1-8
-        let to_file = (self.to % 8) as u8 + b'a';     // converting file to a-h
-        let to_rank = (self.to / 8) as u8 + 1;        // rank as 1-8
+        let from_file = (self.from % 8) + b'a'; // converting file to a-h
+        let from_rank = (self.from / 8) + 1;    // rank as 1-8
+        let to_file = (self.to % 8) + b'a';     // converting file to a-h
+        let to_rank = (self.to / 8) + 1;        // rank as 1-8
+        let promotion = match self.promotion {
+            Some(PieceType::Queen) => "q",
+            Some(PieceType::Rook) => "r",
+            Some(PieceType::Bishop) => "b",
+            Some(PieceType::Knight) => "n",
+            _ => "",
+        };
     
-        format!("{}{}{}{}", from_file as char, from_rank, to_file as char, to_rank)
+        format!("{}{}{}{}{}", from_file as char, from_rank, to_file as char, to_rank, promotion)
     }
 }
 
 // Move scoring structure
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct ScoredMove {
     pub mv: Move,
-    pub score: i32,
+    pub score: Score,
+    pub pv: Vec<Move>, // The line the score is based on, starting with mv
 }
 
 impl ScoredMove {
     // Constructor method for ScoredMove
-    pub fn new(mv: Move, score: i32) -> Self {
-        Self { mv, score }
+    pub fn new(mv: Move, score: Score, pv: Vec<Move>) -> Self {
+        Self { mv, score, pv }
     }
     
 }
@@ -0,0 +1,254 @@
+// movepick.rs
+// Move ordering. Alpha-beta prunes the most when the best move is searched first, so moves are
+// handed to the search in stages: the hash move, then captures that don't lose material by MVV-LVA,
+// then the killer moves of the ply, then the remaining quiet moves by how often they caused cutoffs
+// before, and last the captures that lose material according to static exchange evaluation.
+use std::fmt;
+
+use crate::board::{side_index, Board};
+use crate::piece::PieceType;
+use crate::r#move::Move;
+use crate::score::MAX_PLY;
+
+// History scores are halved when one gets this big, so recent cutoffs keep counting for more
+const HISTORY_LIMIT: i32 = 1 << 20;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Stage {
+    HashMove,
+    Captures,
+    Killers,
+    Quiets,
+    BadCaptures,
+    Done,
+}
+
+// Quiet moves that caused a beta cutoff, two per ply. A move that refuted one position
+// often refutes its siblings too.
+#[derive(Clone, Debug)]
+pub struct Killers {
+    moves: Vec<[Option<Move>; 2]>,
+}
+
+impl Default for Killers {
+    fn default() -> Self {
+        Self { moves: vec![[None; 2]; MAX_PLY] }
+    }
+}
+
+impl Killers {
+    pub fn get(&self, ply: usize) -> [Option<Move>; 2] {
+        self.moves.get(ply).copied().unwrap_or([None; 2])
+    }
+
+    // Remembers a cutoff move, pushing out the older of the two
+    pub fn store(&mut self, ply: usize, mv: Move) {
+        if let Some(slot) = self.moves.get_mut(ply) {
+            if slot[0] != Some(mv) {
+                slot[1] = slot[0];
+                slot[0] = Some(mv);
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.moves.fill([None; 2]);
+    }
+}
+
+// How good each quiet move has been across the whole search, indexed by side, from and to square
+#[derive(Clone)]
+pub struct History {
+    table: Vec<i32>,
+}
+
+impl fmt::Debug for History {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("History").field("entries", &self.table.iter().filter(|&&score| score != 0).count()).finish()
+    }
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self { table: vec![0; 2 * 64 * 64] }
+    }
+}
+
+impl History {
+    pub fn get(&self, side: bool, mv: Move) -> i32 {
+        self.table[Self::index(side, mv)]
+    }
+
+    // Rewards a quiet move that caused a cutoff; deeper searches count for more
+    pub fn reward(&mut self, side: bool, mv: Move, depth: usize) {
+        let index = Self::index(side, mv);
+        self.table[index] += (depth * depth) as i32;
+
+        if self.table[index] >= HISTORY_LIMIT {
+            for score in &mut self.table {
+                *score /= 2;
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.table.fill(0);
+    }
+
+    fn index(side: bool, mv: Move) -> usize {
+        side_index(side) * 64 * 64 + mv.from as usize * 64 + mv.to as usize
+    }
+}
+
+// Most valuable victim, least valuable attacker: win the biggest piece, preferably with the smallest.
+// Promotions count the value they add.
+pub fn mvv_lva(board: &Board, mv: Move) -> i32 {
+    let victim = board.captured_piece_type(&mv).map_or(0, |piece_type| piece_type.value());
+    let promotion = mv.promotion.map_or(0, |piece_type| piece_type.value() - PieceType::Pawn.value());
+    let attacker = if mv.piece_type == PieceType::King { 1000 } else { mv.piece_type.value() };
+    (victim + promotion) * 10 - attacker / 10
+}
+
+// Hands out the legal moves of a position one at a time, best guesses first
+pub struct MovePicker {
+    stage: Stage,
+    hash_move: Option<Move>,
+    killers: [Option<Move>; 2],
+    captures: Vec<(Move, i32)>,     // Captures and promotions with their MVV-LVA scores
+    quiets: Vec<(Move, i32)>,       // Everything else with its history score
+    bad_captures: Vec<(Move, i32)>, // Captures that lose material, with their exchange scores
+}
+
+impl MovePicker {
+    pub fn new(board: &Board, moves: Vec<Move>, hash_move: Option<Move>, killers: [Option<Move>; 2], history: &History) -> Self {
+        let mut captures = Vec::new();
+        let mut quiets = Vec::new();
+        let mut bad_captures = Vec::new();
+        let mut found_hash_move = None;
+
+        for mv in moves {
+            if Some(mv) == hash_move {
+                found_hash_move = Some(mv);
+            } else if mv.promotion.is_some() {
+                captures.push((mv, mvv_lva(board, mv)));
+            } else if board.is_capture(&mv) {
+                let exchange = board.see(mv);
+                if exchange < 0 {
+                    bad_captures.push((mv, exchange));
+                } else {
+                    captures.push((mv, mvv_lva(board, mv)));
+                }
+            } else {
+                quiets.push((mv, history.get(board.side_to_move, mv)));
+            }
+        }
+
+        Self {
+            stage: Stage::HashMove,
+            hash_move: found_hash_move,
+            killers,
+            captures,
+            quiets,
+            bad_captures,
+        }
+    }
+
+    // Takes the highest scored move out of the list. A full sort is wasted work when the
+    // first few moves already produce a cutoff.
+    fn pick_best(moves: &mut Vec<(Move, i32)>) -> Option<Move> {
+        let (index, _) = moves.iter().enumerate().max_by_key(|(_, (_, score))| *score)?;
+        Some(moves.swap_remove(index).0)
+    }
+}
+
+impl Iterator for MovePicker {
+    type Item = Move;
+
+    fn next(&mut self) -> Option<Move> {
+        loop {
+            match self.stage {
+                Stage::HashMove => {
+                    self.stage = Stage::Captures;
+                    if let Some(mv) = self.hash_move {
+                        return Some(mv);
+                    }
+                },
+                Stage::Captures => match Self::pick_best(&mut self.captures) {
+                    Some(mv) => return Some(mv),
+                    None => self.stage = Stage::Killers,
+                },
+                Stage::Killers => {
+                    // Killers are only tried if they are quiet moves that are legal here
+                    let killer = self.killers.iter_mut().find_map(|killer| killer.take()).and_then(|killer| {
+                        let index = self.quiets.iter().position(|&(mv, _)| mv == killer)?;
+                        Some(self.quiets.swap_remove(index).0)
+                    });
+                    match killer {
+                        Some(mv) => return Some(mv),
+                        None if self.killers.iter().all(Option::is_none) => self.stage = Stage::Quiets,
+                        None => {},
+                    }
+                },
+                Stage::Quiets => match Self::pick_best(&mut self.quiets) {
+                    Some(mv) => return Some(mv),
+                    None => self.stage = Stage::BadCaptures,
+                },
+                Stage::BadCaptures => match Self::pick_best(&mut self.bad_captures) {
+                    Some(mv) => return Some(mv),
+                    None => self.stage = Stage::Done,
+                },
+                Stage::Done => return None,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::WHITE;
+
+    // Black's rook on b5 hangs, and d5 is defended by the e6 pawn: exd5 trades evenly while Nxd5 and
+    // Qxd5 lose material
+    const POSITION: &str = "4k3/8/4p3/1r1p4/4P3/2N5/8/3QK3 w - - 0 1";
+
+    fn uci(board: &mut Board, uci_move: &str) -> Move {
+        board.parse_uci_move(uci_move).unwrap()
+    }
+
+    fn picked(board: &mut Board, hash_move: &str, killers: [Option<Move>; 2], history: &History) -> Vec<String> {
+        let hash_move = uci(board, hash_move);
+        let moves = board.generate_legal_moves();
+        let picker = MovePicker::new(board, moves, Some(hash_move), killers, history);
+        picker.map(|mv| mv.get_uci()).collect()
+    }
+
+    #[test]
+    fn hands_out_moves_stage_by_stage() {
+        let mut board = Board::from_fen(POSITION).unwrap();
+        let mut history = History::default();
+        history.reward(WHITE, uci(&mut board, "e1f2"), 5);
+        history.reward(WHITE, uci(&mut board, "e1d2"), 3);
+        // The second killer was a cutoff somewhere else and is not legal here
+        let killers = [Some(uci(&mut board, "d1g4")), Some(Move::new(0, 8, PieceType::Rook, None))];
+
+        let moves = picked(&mut board, "d1h5", killers, &history);
+        assert_eq!(moves.len(), board.generate_legal_moves().len());
+        assert_eq!(&moves[..4], ["d1h5", "c3b5", "e4d5", "d1g4"]);
+        assert_eq!(&moves[4..6], ["e1f2", "e1d2"]);
+        assert_eq!(&moves[moves.len() - 2..], ["c3d5", "d1d5"]);
+    }
+
+    #[test]
+    fn hash_move_is_only_handed_out_once() {
+        let mut board = Board::from_fen(POSITION).unwrap();
+        let killers = [Some(uci(&mut board, "d1g4")), None];
+
+        for hash_move in ["e4d5", "d1g4", "c3d5"] {
+            let moves = picked(&mut board, hash_move, killers, &History::default());
+            assert_eq!(moves[0], hash_move);
+            assert_eq!(moves.iter().filter(|&mv| mv == hash_move).count(), 1, "{} was handed out twice", hash_move);
+            assert_eq!(moves.len(), board.generate_legal_moves().len());
+        }
+    }
+}
@@ -0,0 +1,217 @@
+// pawns.rs
+// Pawn structure evaluation. Pawns move slowly and never backwards, so the same structure comes up
+// again and again during a search; its score is kept in a pawn hash table keyed by the board's
+// pawn key. Only terms that depend on nothing but the pawns are cached. The king proximity of
+// passed pawns is added on top, since it changes with every king move.
+use std::fmt;
+
+use crate::attacks::pawn_attacks;
+use crate::board::{file_mask, rank_mask, side_index, Board, BLACK, WHITE};
+use crate::eval::TaperedScore;
+use crate::piece::PieceType;
+use crate::utils::{get_file, get_rank, pop_lsb};
+
+// Number of entries in a pawn hash table, a power of two
+const PAWN_TABLE_SIZE: usize = 1 << 14;
+
+const DOUBLED_PENALTY: TaperedScore = TaperedScore::new(-10, -20);
+const ISOLATED_PENALTY: TaperedScore = TaperedScore::new(-10, -15);
+const BACKWARD_PENALTY: TaperedScore = TaperedScore::new(-8, -10);
+
+// Bonuses by rank, counted from the side's own back rank
+const SUPPORTED_BONUS: [TaperedScore; 8] = [
+    TaperedScore::new(0, 0),
+    TaperedScore::new(0, 0),
+    TaperedScore::new(7, 4),
+    TaperedScore::new(9, 6),
+    TaperedScore::new(14, 10),
+    TaperedScore::new(24, 18),
+    TaperedScore::new(40, 30),
+    TaperedScore::new(0, 0),
+];
+const PHALANX_BONUS: [TaperedScore; 8] = [
+    TaperedScore::new(0, 0),
+    TaperedScore::new(2, 0),
+    TaperedScore::new(4, 2),
+    TaperedScore::new(6, 4),
+    TaperedScore::new(10, 8),
+    TaperedScore::new(20, 15),
+    TaperedScore::new(35, 30),
+    TaperedScore::new(0, 0),
+];
+const PASSED_BONUS: [TaperedScore; 8] = [
+    TaperedScore::new(0, 0),
+    TaperedScore::new(5, 10),
+    TaperedScore::new(5, 15),
+    TaperedScore::new(10, 25),
+    TaperedScore::new(25, 45),
+    TaperedScore::new(45, 75),
+    TaperedScore::new(70, 120),
+    TaperedScore::new(0, 0),
+];
+
+// Endgame bonus per square of distance between the enemy king and the square in front of a
+// passed pawn, and penalty per square of distance for the own king; scaled by how far the pawn is
+const ENEMY_KING_DISTANCE_BONUS: i32 = 5;
+const OWN_KING_DISTANCE_PENALTY: i32 = 2;
+
+fn adjacent_files(file: u8) -> u64 {
+    let left = if file > 0 { file_mask(file - 1) } else { 0 };
+    let right = if file < 7 { file_mask(file + 1) } else { 0 };
+    left | right
+}
+
+// Every square on the ranks in front of `rank`, seen from the side
+fn ranks_ahead(side: bool, rank: u8) -> u64 {
+    if side == WHITE {
+        if rank >= 7 { 0 } else { u64::MAX << ((rank + 1) * 8) }
+    } else if rank == 0 {
+        0
+    } else {
+        u64::MAX >> ((8 - rank) * 8)
+    }
+}
+
+// The rank counted from the side's own back rank
+fn relative_rank(side: bool, square: u8) -> usize {
+    let rank = get_rank(square);
+    (if side == WHITE { rank } else { 7 - rank }) as usize
+}
+
+fn distance(a: u8, b: u8) -> i32 {
+    get_rank(a).abs_diff(get_rank(b)).max(get_file(a).abs_diff(get_file(b))) as i32
+}
+
+// The pawn structure score of one position
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PawnEntry {
+    key: u64,
+    pub scores: [TaperedScore; 2], // Per side, White first
+    pub passed: [u64; 2],          // Passed pawns per side, White first
+}
+
+impl PawnEntry {
+    pub fn score(&self, side: bool) -> TaperedScore {
+        self.scores[side_index(side)]
+    }
+
+    pub fn passed_pawns(&self, side: bool) -> u64 {
+        self.passed[side_index(side)]
+    }
+}
+
+// Scores the pawn structure of both sides from scratch
+pub fn evaluate_pawns(board: &Board) -> PawnEntry {
+    let mut entry = PawnEntry { key: board.pawn_key, ..PawnEntry::default() };
+
+    for side in [WHITE, BLACK] {
+        let own_pawns = board.pieces(PieceType::Pawn, side);
+        let enemy_pawns = board.pieces(PieceType::Pawn, !side);
+        let mut score = TaperedScore::default();
+        let mut passed = 0;
+
+        // Every pawn after the first on a file is in the way of the others
+        for file in 0..8 {
+            let count = (own_pawns & file_mask(file)).count_ones() as i32;
+            if count > 1 {
+                score += DOUBLED_PENALTY * (count - 1);
+            }
+        }
+
+        let mut pawns = own_pawns;
+        while pawns != 0 {
+            let square = pop_lsb(&mut pawns);
+            let file = get_file(square);
+            let rank = get_rank(square);
+            let relative_rank = relative_rank(side, square);
+            let neighbours = own_pawns & adjacent_files(file);
+
+            // Nothing in the way or able to take it on the way to promotion
+            if enemy_pawns & (file_mask(file) | adjacent_files(file)) & ranks_ahead(side, rank) == 0 {
+                passed |= 1u64 << square;
+                score += PASSED_BONUS[relative_rank];
+            }
+
+            if neighbours == 0 {
+                // No pawn on a neighbouring file can ever defend it
+                score += ISOLATED_PENALTY;
+            } else if neighbours & !ranks_ahead(side, rank) == 0 {
+                // Its neighbours have all gone past it, and an enemy pawn stops it from catching up
+                let stop_square = if side == WHITE { square + 8 } else { square - 8 };
+                if pawn_attacks(stop_square, side) & enemy_pawns != 0 {
+                    score += BACKWARD_PENALTY;
+                }
+            }
+
+            // Defended by a pawn, or standing next to one that can defend it by advancing
+            if pawn_attacks(square, !side) & own_pawns != 0 {
+                score += SUPPORTED_BONUS[relative_rank];
+            }
+            if neighbours & rank_mask(rank) != 0 {
+                score += PHALANX_BONUS[relative_rank];
+            }
+        }
+
+        entry.scores[side_index(side)] = score;
+        entry.passed[side_index(side)] = passed;
+    }
+
+    entry
+}
+
+// In the endgame a passed pawn is worth more the further the enemy king is from stopping it and
+// the closer its own king is to escorting it
+pub fn passed_pawn_king_proximity(board: &Board, entry: &PawnEntry, side: bool) -> TaperedScore {
+    let own_king = board.pieces(PieceType::King, side).trailing_zeros() as u8;
+    let enemy_king = board.pieces(PieceType::King, !side).trailing_zeros() as u8;
+
+    let mut score = TaperedScore::default();
+    let mut passed = entry.passed_pawns(side);
+    while passed != 0 {
+        let square = pop_lsb(&mut passed);
+        let relative_rank = relative_rank(side, square) as i32;
+        if relative_rank < 3 {
+            continue;
+        }
+
+        let stop_square = if side == WHITE { square + 8 } else { square - 8 };
+        let weight = relative_rank - 2;
+        score.eg += weight
+            * (distance(enemy_king, stop_square) * ENEMY_KING_DISTANCE_BONUS - distance(own_king, stop_square) * OWN_KING_DISTANCE_PENALTY);
+    }
+    score
+}
+
+// Pawn hash table: pawn structures already scored, replaced whenever another one lands in the slot
+#[derive(Clone)]
+pub struct PawnTable {
+    entries: Vec<PawnEntry>,
+}
+
+impl fmt::Debug for PawnTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PawnTable").field("entries", &self.entries.iter().filter(|entry| entry.key != 0).count()).finish()
+    }
+}
+
+impl Default for PawnTable {
+    fn default() -> Self {
+        Self { entries: vec![PawnEntry::default(); PAWN_TABLE_SIZE] }
+    }
+}
+
+impl PawnTable {
+    // The pawn structure score of the board, from the table if it was scored before
+    pub fn probe(&mut self, board: &Board) -> PawnEntry {
+        let index = board.pawn_key as usize & (PAWN_TABLE_SIZE - 1);
+        let entry = &mut self.entries[index];
+        if entry.key != board.pawn_key {
+            *entry = evaluate_pawns(board);
+        }
+        *entry
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.fill(PawnEntry::default());
+    }
+}
@@ -0,0 +1,698 @@
+// pgn.rs
+// Reading and writing games in Portable Game Notation.
+//
+// PgnReader pulls games one at a time out of any BufRead, so a whole database never has to be
+// in memory. The mainline of each game is replayed on a Board; variations, comments and NAGs are
+// skipped. A game that cannot be read comes back as an error with the line and column of the
+// problem, and reading carries on with the next game.
+use std::fmt;
+use std::io::BufRead;
+use std::time::Duration;
+
+use crate::board::{Board, WHITE};
+use crate::fen::FenError;
+use crate::r#move::Move;
+use crate::san::SanError;
+
+#[derive(Clone, Debug)]
+pub struct PgnGame {
+    pub tags: Vec<(String, String)>, // Tag pairs in the order they appear
+    pub start: Board,                // Position before the first move, from the FEN tag if there is one
+    pub board: Board,                // Position after the last mainline move
+    pub moves: Vec<Move>,            // The mainline
+    pub result: Option<String>,      // "1-0", "0-1", "1/2-1/2" or "*", if the movetext has one
+}
+
+impl PgnGame {
+    // Value of the first tag with the given name
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags.iter().find(|(tag, _)| tag == name).map(|(_, value)| value.as_str())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum PgnErrorKind {
+    Io(String),
+    BadTag,                       // A tag pair that isn't [Name "value"]
+    BadFen(FenError),             // The FEN tag doesn't describe a valid position
+    BadMove(SanError),            // A mainline move that can't be played
+    UnexpectedChar(char),
+    UnterminatedComment,          // A { comment still open at the end of the input
+    UnbalancedVariation,          // A ) without its (, or a ( never closed
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PgnError {
+    pub line: usize,   // 1-based
+    pub column: usize, // 1-based, counted in characters
+    pub kind: PgnErrorKind,
+}
+
+impl fmt::Display for PgnError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}, column {}: ", self.line, self.column)?;
+        match &self.kind {
+            PgnErrorKind::Io(message) => write!(f, "read error: {}", message),
+            PgnErrorKind::BadTag => write!(f, "malformed tag pair"),
+            PgnErrorKind::BadFen(err) => write!(f, "invalid FEN tag: {}", err),
+            PgnErrorKind::BadMove(err) => write!(f, "{}", err),
+            PgnErrorKind::UnexpectedChar(c) => write!(f, "unexpected character '{}'", c),
+            PgnErrorKind::UnterminatedComment => write!(f, "comment is never closed"),
+            PgnErrorKind::UnbalancedVariation => write!(f, "unbalanced parentheses"),
+        }
+    }
+}
+
+impl std::error::Error for PgnError {}
+
+enum Token {
+    Tag(String, String),
+    Comment,
+    Nag,
+    VariationStart,
+    VariationEnd,
+    Move(String),   // A SAN move with any move number in front of it removed
+    MoveNumber,
+    Result(String),
+}
+
+pub struct PgnReader<R: BufRead> {
+    reader: R,
+    chars: Vec<char>,              // The current line
+    pos: usize,                    // Index of the next character in `chars`
+    line: usize,
+    eof: bool,
+    io_error: Option<String>,
+    pending: Option<(Token, usize, usize)>, // A tag read while finishing the previous game
+}
+
+impl<R: BufRead> PgnReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            chars: Vec::new(),
+            pos: 0,
+            line: 0,
+            eof: false,
+            io_error: None,
+            pending: None,
+        }
+    }
+
+    // The next character, reading another line once the current one is used up
+    fn peek(&mut self) -> Option<char> {
+        while self.pos >= self.chars.len() {
+            if self.eof {
+                return None;
+            }
+
+            let mut text = String::new();
+            match self.reader.read_line(&mut text) {
+                Ok(0) => self.eof = true,
+                Ok(_) => {
+                    self.chars = text.chars().collect();
+                    self.pos = 0;
+                    self.line += 1;
+                },
+                Err(err) => {
+                    self.io_error = Some(err.to_string());
+                    self.eof = true;
+                },
+            }
+        }
+
+        Some(self.chars[self.pos])
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        Some(c)
+    }
+
+    fn error(&self, line: usize, column: usize, kind: PgnErrorKind) -> PgnError {
+        PgnError { line, column, kind }
+    }
+
+    // Reads the next token along with the line and column it starts at. Every error consumes
+    // at least one character, so the caller can keep asking for tokens after one.
+    fn next_token(&mut self) -> Result<Option<(Token, usize, usize)>, PgnError> {
+        loop {
+            let Some(c) = self.peek() else {
+                return Ok(None);
+            };
+            let (line, column) = (self.line, self.pos + 1);
+
+            if c.is_whitespace() {
+                self.bump();
+                continue;
+            }
+
+            // Rest-of-line comments, and % escape lines which are for other programs
+            if c == ';' || (c == '%' && column == 1) {
+                while let Some(c) = self.bump() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+                continue;
+            }
+
+            let token = match c {
+                '[' => {
+                    self.bump();
+                    let (name, value) = self.read_tag().ok_or_else(|| self.error(line, column, PgnErrorKind::BadTag))?;
+                    Token::Tag(name, value)
+                },
+                '{' => {
+                    while self.bump() != Some('}') {
+                        if self.peek().is_none() {
+                            return Err(self.error(line, column, PgnErrorKind::UnterminatedComment));
+                        }
+                    }
+                    Token::Comment
+                },
+                '(' => {
+                    self.bump();
+                    Token::VariationStart
+                },
+                ')' => {
+                    self.bump();
+                    Token::VariationEnd
+                },
+                '$' => {
+                    self.bump();
+                    while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+                        self.bump();
+                    }
+                    Token::Nag
+                },
+                c if is_symbol_char(c) => {
+                    let mut symbol = String::new();
+                    while let Some(c) = self.peek().filter(|&c| is_symbol_char(c)) {
+                        symbol.push(c);
+                        self.bump();
+                    }
+                    classify_symbol(symbol)
+                },
+                c => {
+                    self.bump();
+                    return Err(self.error(line, column, PgnErrorKind::UnexpectedChar(c)));
+                },
+            };
+
+            return Ok(Some((token, line, column)));
+        }
+    }
+
+    // Reads the rest of a tag pair after its opening [
+    fn read_tag(&mut self) -> Option<(String, String)> {
+        self.skip_spaces();
+        let mut name = String::new();
+        while let Some(c) = self.peek().filter(|c| c.is_alphanumeric() || *c == '_') {
+            name.push(c);
+            self.bump();
+        }
+
+        self.skip_spaces();
+        if name.is_empty() || self.bump()? != '"' {
+            return None;
+        }
+
+        let mut value = String::new();
+        loop {
+            match self.bump()? {
+                '"' => break,
+                '\\' => value.push(self.bump()?), // \" and \\ are the only escapes
+                '\n' => return None,
+                c => value.push(c),
+            }
+        }
+
+        self.skip_spaces();
+        if self.bump()? != ']' {
+            return None;
+        }
+
+        Some((name, value))
+    }
+
+    fn skip_spaces(&mut self) {
+        while self.peek().is_some_and(|c| c == ' ' || c == '\t') {
+            self.bump();
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for PgnReader<R> {
+    type Item = Result<PgnGame, PgnError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut tags: Vec<(String, String)> = Vec::new();
+        let mut fen_position = (0, 0);
+        let mut boards: Option<(Board, Board)> = None; // Start and current position, once the movetext begins
+        let mut moves = Vec::new();
+        let mut result = None;
+        let mut depth = 0; // How many variations deep we are
+        let mut error: Option<PgnError> = None;
+        let mut seen_anything = false;
+
+        loop {
+            let (token, line, column) = match self.pending.take().map(|token| Ok(Some(token))).unwrap_or_else(|| self.next_token()) {
+                Ok(Some(token)) => token,
+                Ok(None) => break,
+                Err(err) => {
+                    error.get_or_insert(err);
+                    seen_anything = true;
+                    continue;
+                },
+            };
+            seen_anything = true;
+
+            if let Token::Tag(name, value) = token {
+                if boards.is_some() && depth == 0 {
+                    // The previous game ended without a result token
+                    self.pending = Some((Token::Tag(name, value), line, column));
+                    break;
+                }
+                if name == "FEN" {
+                    fen_position = (line, column);
+                }
+                tags.push((name, value));
+                continue;
+            }
+
+            let (_, board) = boards.get_or_insert_with(|| {
+                let start = start_position(&tags).unwrap_or_else(|err| {
+                    error.get_or_insert(PgnError { line: fen_position.0, column: fen_position.1, kind: PgnErrorKind::BadFen(err) });
+                    Board::new()
+                });
+                (start.clone(), start)
+            });
+
+            match token {
+                Token::VariationStart => depth += 1,
+                Token::VariationEnd => {
+                    if depth == 0 {
+                        error.get_or_insert(self.error(line, column, PgnErrorKind::UnbalancedVariation));
+                    } else {
+                        depth -= 1;
+                    }
+                },
+                Token::Move(san) if depth == 0 && error.is_none() => match board.parse_san(&san) {
+                    Ok(mv) => {
+                        board.make_move(mv);
+                        moves.push(mv);
+                    },
+                    Err(err) => error = Some(self.error(line, column, PgnErrorKind::BadMove(err))),
+                },
+                Token::Result(token) if depth == 0 => {
+                    result = Some(token);
+                    break;
+                },
+                _ => {},
+            }
+        }
+
+        if depth > 0 {
+            error.get_or_insert(self.error(self.line, self.pos + 1, PgnErrorKind::UnbalancedVariation));
+        }
+        if let Some(message) = self.io_error.take() {
+            error.get_or_insert(self.error(self.line, self.pos + 1, PgnErrorKind::Io(message)));
+            seen_anything = true;
+        }
+
+        if !seen_anything {
+            return None;
+        }
+        if let Some(err) = error {
+            return Some(Err(err));
+        }
+
+        // A game with tags but no movetext at all
+        let (start, board) = match boards {
+            Some(boards) => boards,
+            None => match start_position(&tags) {
+                Ok(start) => (start.clone(), start),
+                Err(err) => return Some(Err(self.error(fen_position.0, fen_position.1, PgnErrorKind::BadFen(err)))),
+            },
+        };
+
+        Some(Ok(PgnGame { tags, start, board, moves, result }))
+    }
+}
+
+// The position a game starts from: the standard one unless there is a FEN tag
+fn start_position(tags: &[(String, String)]) -> Result<Board, FenError> {
+    match tags.iter().find(|(name, _)| name == "FEN") {
+        Some((_, fen)) => Board::from_fen(fen),
+        None => Ok(Board::new()),
+    }
+}
+
+fn is_symbol_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "_+#=:-/!?.*".contains(c)
+}
+
+// Sorts a symbol into a result, an annotation, a bare move number, or a move. A move number glued to
+// its move, as in "1.e4" or "12...Nf6", is dropped.
+fn classify_symbol(symbol: String) -> Token {
+    if matches!(symbol.as_str(), "1-0" | "0-1" | "1/2-1/2" | "*") {
+        return Token::Result(symbol);
+    }
+
+    // Move annotations written apart from their move, like "e4 !?"
+    if symbol.chars().all(|c| c == '!' || c == '?') {
+        return Token::Nag;
+    }
+
+    let digits = symbol.chars().take_while(|c| c.is_ascii_digit()).count();
+    let rest = &symbol[digits..];
+    if digits > 0 && rest.starts_with('.') {
+        let san = rest.trim_start_matches('.');
+        return if san.is_empty() { Token::MoveNumber } else { Token::Move(san.to_string()) };
+    }
+    if digits == symbol.len() {
+        return Token::MoveNumber;
+    }
+
+    Token::Move(symbol)
+}
+
+// Extra information written after a move in the movetext
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MoveAnnotation {
+    pub comment: Option<String>,
+    pub eval: Option<i32>,        // Centipawns from white's point of view, written as [%eval 0.35]
+    pub clock: Option<Duration>,  // Time left after the move, written as [%clk 0:03:12]
+}
+
+// The tags every PGN game has, in the order the standard wants them
+const SEVEN_TAG_ROSTER: [&str; 7] = ["Event", "Site", "Date", "Round", "White", "Black", "Result"];
+
+const MAX_LINE_LENGTH: usize = 80;
+
+// Writes a game as PGN. Tags missing from `tags` get the standard placeholders, FEN and SetUp
+// are added when the game doesn't start from the initial position, and `annotations` may be
+// shorter than `moves`. The result is read from the final position when the game is over on
+// the board; otherwise the Result tag given by the caller is used, or "*" without one.
+pub fn write_pgn(tags: &[(String, String)], start: &Board, moves: &[Move], annotations: &[MoveAnnotation]) -> String {
+    let given_tag = |name: &str| tags.iter().find(|(tag, _)| tag == name).map(|(_, value)| value.as_str());
+
+    // Replay the game, writing the movetext as a list of words to wrap afterwards
+    let mut board = start.clone();
+    let mut words = Vec::new();
+    for (ply, &mv) in moves.iter().enumerate() {
+        if board.side_to_move == WHITE {
+            words.push(format!("{}.", board.fullmove_number));
+        } else if ply == 0 {
+            words.push(format!("{}...", board.fullmove_number));
+        }
+
+        words.push(board.move_to_san(mv));
+        board.make_move(mv);
+
+        if let Some(annotation) = annotations.get(ply) {
+            let comment = annotation_comment(annotation);
+            if !comment.is_empty() {
+                let comment = format!("{{{}}}", comment);
+                words.extend(comment.split_whitespace().map(str::to_string));
+                // A comment interrupts the move pair, so black's move needs its number again
+                if board.side_to_move != WHITE && ply + 1 < moves.len() {
+                    words.push(format!("{}...", board.fullmove_number));
+                }
+            }
+        }
+    }
+
+    let result = board.game_result().map(|result| result.pgn_token())
+        .or_else(|| given_tag("Result").filter(|result| matches!(*result, "1-0" | "0-1" | "1/2-1/2")))
+        .unwrap_or("*");
+    words.push(result.to_string());
+
+    let mut pgn = String::new();
+    for name in SEVEN_TAG_ROSTER {
+        let value = match name {
+            "Result" => result,
+            "Date" => given_tag(name).unwrap_or("????.??.??"),
+            _ => given_tag(name).unwrap_or("?"),
+        };
+        pgn.push_str(&tag_line(name, value));
+    }
+
+    let start_fen = start.to_fen();
+    if start_fen != Board::new().to_fen() {
+        pgn.push_str(&tag_line("SetUp", "1"));
+        pgn.push_str(&tag_line("FEN", &start_fen));
+    }
+
+    for (name, value) in tags {
+        if !SEVEN_TAG_ROSTER.contains(&name.as_str()) && name != "SetUp" && name != "FEN" {
+            pgn.push_str(&tag_line(name, value));
+        }
+    }
+
+    pgn.push('\n');
+    pgn.push_str(&wrap_words(&words));
+    pgn.push('\n');
+    pgn
+}
+
+fn tag_line(name: &str, value: &str) -> String {
+    format!("[{} \"{}\"]\n", name, value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+// The text of the comment for a move, without braces
+fn annotation_comment(annotation: &MoveAnnotation) -> String {
+    let mut parts = Vec::new();
+    if let Some(eval) = annotation.eval {
+        parts.push(format!("[%eval {:.2}]", eval as f64 / 100.0));
+    }
+    if let Some(clock) = annotation.clock {
+        let seconds = clock.as_secs();
+        parts.push(format!("[%clk {}:{:02}:{:02}]", seconds / 3600, seconds / 60 % 60, seconds % 60));
+    }
+    if let Some(comment) = &annotation.comment {
+        // A closing brace would end the comment early
+        parts.push(comment.replace('}', ")"));
+    }
+    parts.join(" ")
+}
+
+// Joins words with spaces, starting a new line before any word that would go past the limit
+fn wrap_words(words: &[String]) -> String {
+    let mut text = String::new();
+    let mut line_length = 0;
+    for word in words {
+        if line_length > 0 && line_length + 1 + word.len() > MAX_LINE_LENGTH {
+            text.push('\n');
+            line_length = 0;
+        } else if line_length > 0 {
+            text.push(' ');
+            line_length += 1;
+        }
+        text.push_str(word);
+        line_length += word.len();
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(pgn: &str) -> Vec<Result<PgnGame, PgnError>> {
+        PgnReader::new(pgn.as_bytes()).collect()
+    }
+
+    fn mainline(game: &PgnGame) -> Vec<String> {
+        game.moves.iter().map(|mv| mv.get_uci()).collect()
+    }
+
+    #[test]
+    fn reads_several_games() {
+        let pgn = "[Event \"First\"]\n[White \"A\"]\n\n1. e4 e5 2. Nf3 1-0\n\n\
+                   [Event \"Second\"]\n\n1. d4 d5 *\n\n\
+                   [Event \"Third\"]\n\n1. c4\n\n\
+                   [Event \"Fourth\"]\n\n1/2-1/2\n";
+        let games = read(pgn);
+        assert_eq!(games.len(), 4);
+
+        let first = games[0].as_ref().unwrap();
+        assert_eq!(first.tag("Event"), Some("First"));
+        assert_eq!(first.tag("White"), Some("A"));
+        assert_eq!(mainline(first), ["e2e4", "e7e5", "g1f3"]);
+        assert_eq!(first.result.as_deref(), Some("1-0"));
+
+        assert_eq!(mainline(games[1].as_ref().unwrap()), ["d2d4", "d7d5"]);
+        assert_eq!(games[1].as_ref().unwrap().result.as_deref(), Some("*"));
+
+        // A game without a result token ends where the next one's tags begin
+        let third = games[2].as_ref().unwrap();
+        assert_eq!(mainline(third), ["c2c4"]);
+        assert_eq!(third.result, None);
+
+        let fourth = games[3].as_ref().unwrap();
+        assert_eq!(fourth.tag("Event"), Some("Fourth"));
+        assert!(fourth.moves.is_empty());
+    }
+
+    #[test]
+    fn skips_comments() {
+        let pgn = "1. e4 {King's pawn,\nspread over ( two lines } e5 ; the rest of the line, 2. a4\n2. Qh5\n% escaped line 2... a5\n2... Nc6 *";
+        let games = read(pgn);
+        assert_eq!(mainline(games[0].as_ref().unwrap()), ["e2e4", "e7e5", "d1h5", "b8c6"]);
+    }
+
+    #[test]
+    fn skips_variations() {
+        // The moves in a variation are not legal in the mainline
+        let pgn = "1. e4 (1. d4 d5 (1... Nf6 2. c4) 2. c4) 1... e5 (1... c5 2. Nf3) 2. Nf3 *";
+        let games = read(pgn);
+        assert_eq!(mainline(games[0].as_ref().unwrap()), ["e2e4", "e7e5", "g1f3"]);
+    }
+
+    #[test]
+    fn skips_nags() {
+        let pgn = "1. e4! $1 e5 ?! 2. Nf3 $14 Nc6!? $32 *";
+        let games = read(pgn);
+        assert_eq!(mainline(games[0].as_ref().unwrap()), ["e2e4", "e7e5", "g1f3", "b8c6"]);
+    }
+
+    #[test]
+    fn starts_from_the_fen_tag() {
+        let pgn = "[SetUp \"1\"]\n[FEN \"4k3/8/8/8/8/8/4P3/4K3 b - - 0 40\"]\n\n40... Kd7 41. e4 *";
+        let games = read(pgn);
+        let game = games[0].as_ref().unwrap();
+        assert_eq!(game.start.to_fen(), "4k3/8/8/8/8/8/4P3/4K3 b - - 0 40");
+        assert_eq!(game.board.to_fen(), "8/3k4/8/8/4P3/8/8/4K3 b - - 0 41");
+    }
+
+    #[test]
+    fn reports_where_a_bad_move_is() {
+        let pgn = "[Event \"Bad\"]\n\n1. e4 e5\n2. Nf3 Ke3 3. Bc4 *\n\n[Event \"Good\"]\n\n1. d4 *\n";
+        let games = read(pgn);
+        assert_eq!(games.len(), 2);
+        assert_eq!(games[0].as_ref().unwrap_err(), &PgnError {
+            line: 4,
+            column: 8,
+            kind: PgnErrorKind::BadMove(SanError::Illegal("Ke3".to_string())),
+        });
+        // Reading carries on with the next game
+        assert_eq!(mainline(games[1].as_ref().unwrap()), ["d2d4"]);
+    }
+
+    #[test]
+    fn reports_malformed_input() {
+        let error = |pgn: &str| read(pgn).remove(0).unwrap_err();
+
+        assert_eq!(error("[Event \"Unclosed]\n1. e4 *"), PgnError { line: 1, column: 1, kind: PgnErrorKind::BadTag });
+        assert_eq!(error("1. e4\n  {never closed"), PgnError { line: 2, column: 3, kind: PgnErrorKind::UnterminatedComment });
+        assert_eq!(error("1. e4 ) e5 *"), PgnError { line: 1, column: 7, kind: PgnErrorKind::UnbalancedVariation });
+        assert_eq!(error("1. e4 @ e5 *"), PgnError { line: 1, column: 7, kind: PgnErrorKind::UnexpectedChar('@') });
+        assert_eq!(error("[FEN \"8/8/8/8/8/8/8/8 w - - 0 1\"]\n1. e4 *").kind, PgnErrorKind::BadFen(FenError::MissingKing(WHITE)));
+    }
+
+    // Plays SAN moves from the given position
+    fn play(start: &Board, sans: &[&str]) -> Vec<Move> {
+        let mut board = start.clone();
+        sans.iter()
+            .map(|san| {
+                let mv = board.parse_san(san).unwrap();
+                board.make_move(mv);
+                mv
+            })
+            .collect()
+    }
+
+    fn tags(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect()
+    }
+
+    #[test]
+    fn writes_seven_tag_roster_in_order() {
+        let start = Board::new();
+        let moves = play(&start, &["e4"]);
+        let given = tags(&[("Annotator", "Me"), ("White", "W"), ("Event", "E"), ("Black", "B")]);
+        let pgn = write_pgn(&given, &start, &moves, &[]);
+
+        let tag_lines: Vec<&str> = pgn.lines().take_while(|line| line.starts_with('[')).collect();
+        assert_eq!(tag_lines, [
+            "[Event \"E\"]",
+            "[Site \"?\"]",
+            "[Date \"????.??.??\"]",
+            "[Round \"?\"]",
+            "[White \"W\"]",
+            "[Black \"B\"]",
+            "[Result \"*\"]",
+            "[Annotator \"Me\"]",
+        ]);
+    }
+
+    #[test]
+    fn numbers_moves_from_a_black_to_move_position() {
+        let start = Board::from_fen("4k3/8/8/8/8/8/4P3/4K3 b - - 0 40").unwrap();
+        let moves = play(&start, &["Kd7", "e4", "Ke6"]);
+        let pgn = write_pgn(&[], &start, &moves, &[]);
+
+        assert!(pgn.contains("[SetUp \"1\"]\n[FEN \"4k3/8/8/8/8/8/4P3/4K3 b - - 0 40\"]\n"));
+        assert!(pgn.ends_with("\n40... Kd7 41. e4 Ke6 *\n"), "{}", pgn);
+    }
+
+    #[test]
+    fn repeats_the_move_number_after_a_comment() {
+        let start = Board::new();
+        let moves = play(&start, &["e4", "e5"]);
+        let annotation = MoveAnnotation { comment: Some("best by test".to_string()), eval: Some(35), clock: None };
+        let pgn = write_pgn(&[], &start, &moves, &[annotation]);
+        assert!(pgn.ends_with("\n1. e4 {[%eval 0.35] best by test} 1... e5 *\n"), "{}", pgn);
+    }
+
+    #[test]
+    fn wraps_movetext_at_80_columns() {
+        let start = Board::new();
+        let knight_dance = ["Nf3", "Nf6", "Ng1", "Ng8"].repeat(10);
+        let moves = play(&start, &knight_dance[..40]);
+        let pgn = write_pgn(&tags(&[("Result", "1/2-1/2")]), &start, &moves, &[]);
+
+        let movetext: Vec<&str> = pgn.lines().skip_while(|line| !line.is_empty()).skip(1).collect();
+        assert!(movetext.len() > 1);
+        assert!(movetext.iter().all(|line| line.len() <= MAX_LINE_LENGTH && !line.starts_with(' ') && !line.ends_with(' ')));
+        // No line was broken before a word that still fitted on it
+        for pair in movetext.windows(2) {
+            let first_word = pair[1].split(' ').next().unwrap();
+            assert!(pair[0].len() + 1 + first_word.len() > MAX_LINE_LENGTH);
+        }
+        assert_eq!(movetext.join(" ").split(' ').count(), 20 + 40 + 1);
+    }
+
+    #[test]
+    fn writes_the_result() {
+        let start = Board::new();
+        let result_token = |pgn: &str| pgn.trim_end().rsplit(' ').next().unwrap().to_string();
+
+        // Checkmate on the board wins over whatever the tags say
+        let fools_mate = play(&start, &["f3", "e5", "g4", "Qh4#"]);
+        let pgn = write_pgn(&tags(&[("Result", "1-0")]), &start, &fools_mate, &[]);
+        assert_eq!(result_token(&pgn), "0-1");
+        assert!(pgn.contains("[Result \"0-1\"]"));
+
+        let moves = play(&start, &["e4"]);
+        assert_eq!(result_token(&write_pgn(&tags(&[("Result", "1-0")]), &start, &moves, &[])), "1-0");
+        assert_eq!(result_token(&write_pgn(&tags(&[("Result", "win")]), &start, &moves, &[])), "*");
+        assert_eq!(result_token(&write_pgn(&[], &start, &moves, &[])), "*");
+    }
+
+    #[test]
+    fn reads_back_what_it_writes() {
+        let start = Board::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1").unwrap();
+        let moves = play(&start, &["O-O", "Bxe2", "Nxe2", "O-O-O", "d6", "hxg2", "dxe7", "gxf1=Q+"]);
+        let pgn = write_pgn(&tags(&[("Event", "Round trip")]), &start, &moves, &[]);
+
+        let games = read(&pgn);
+        let game = games[0].as_ref().unwrap();
+        assert_eq!(game.tag("Event"), Some("Round trip"));
+        assert_eq!(game.start.to_fen(), start.to_fen());
+        assert_eq!(game.moves, moves);
+    }
+}
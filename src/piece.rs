@@ -6,4 +6,23 @@ pub enum PieceType {
     Rook,
     Queen,
     King,
-}
\ No newline at end of file
+}
+
+impl PieceType {
+    // Material value in centipawns. The king is never traded, so it has no material value.
+    pub fn value(&self) -> i32 {
+        match self {
+            PieceType::Pawn => 100,
+            PieceType::Knight => 300,
+            PieceType::Bishop => 300,
+            PieceType::Rook => 500,
+            PieceType::Queen => 900,
+            PieceType::King => 0,
+        }
+    }
+
+    // Position in the order Pawn, Knight, Bishop, Rook, Queen, King, for tables with an entry per piece type
+    pub fn index(&self) -> usize {
+        *self as usize
+    }
+}
@@ -0,0 +1,274 @@
+// san.rs
+// Standard Algebraic Notation, e.g. Nbd7, exd6, e8=Q+, O-O-O#
+use std::fmt;
+
+use crate::board::{Board, WHITE};
+use crate::fen::piece_char;
+use crate::piece::PieceType;
+use crate::r#move::Move;
+use crate::utils::{get_file, get_rank, parse_square, square_name};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SanError {
+    Empty,
+    Syntax(String),    // Not shaped like a SAN move at all
+    Illegal(String),   // Well formed, but no legal move matches it
+    Ambiguous(String), // More than one legal move matches it
+}
+
+impl fmt::Display for SanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SanError::Empty => write!(f, "empty move"),
+            SanError::Syntax(san) => write!(f, "cannot read '{}' as a move", san),
+            SanError::Illegal(san) => write!(f, "'{}' is not a legal move", san),
+            SanError::Ambiguous(san) => write!(f, "'{}' matches more than one legal move", san),
+        }
+    }
+}
+
+impl std::error::Error for SanError {}
+
+impl Board {
+    // Writes a legal move of the side to move in SAN
+    pub fn move_to_san(&mut self, mv: Move) -> String {
+        let mut san = String::new();
+
+        if mv.piece_type == PieceType::King && get_file(mv.from).abs_diff(get_file(mv.to)) == 2 {
+            san.push_str(if get_file(mv.to) == 6 { "O-O" } else { "O-O-O" });
+        } else {
+            let capture = self.is_capture(&mv);
+
+            if mv.piece_type == PieceType::Pawn {
+                // Pawn captures are named by the file they start from
+                if capture {
+                    san.push((b'a' + get_file(mv.from)) as char);
+                }
+            } else {
+                san.push(piece_char(mv.piece_type, WHITE));
+                san.push_str(&self.disambiguation(mv));
+            }
+
+            if capture {
+                san.push('x');
+            }
+            san.push_str(&square_name(mv.to));
+
+            if let Some(promotion) = mv.promotion {
+                san.push('=');
+                san.push(piece_char(promotion, WHITE));
+            }
+        }
+
+        let undo_state = self.make_move(mv);
+        if self.is_in_check(self.side_to_move) {
+            san.push(if self.generate_legal_moves().is_empty() { '#' } else { '+' });
+        }
+        self.unmake_move(mv, undo_state);
+
+        san
+    }
+
+    // Finds the legal move a SAN string describes. Besides strict SAN this accepts castling
+    // with zeros (0-0), a missing or extra 'x', a promotion without '=', long algebraic
+    // moves such as Ng1-f3, and trailing check marks or annotations like "!?".
+    pub fn parse_san(&mut self, san: &str) -> Result<Move, SanError> {
+        let trimmed = san.trim().trim_end_matches(['+', '#', '!', '?']);
+        if trimmed.is_empty() {
+            return Err(SanError::Empty);
+        }
+        let syntax_error = || SanError::Syntax(san.to_string());
+
+        let legal_moves = self.generate_legal_moves();
+
+        let castling_file = match trimmed {
+            "O-O" | "0-0" | "o-o" => Some(6),
+            "O-O-O" | "0-0-0" | "o-o-o" => Some(2),
+            _ => None,
+        };
+        if let Some(file) = castling_file {
+            return legal_moves
+                .into_iter()
+                .find(|mv| mv.piece_type == PieceType::King && get_file(mv.from) == 4 && get_file(mv.to) == file)
+                .ok_or_else(|| SanError::Illegal(san.to_string()));
+        }
+
+        let mut chars: Vec<char> = trimmed.chars().filter(|&c| c != 'x' && c != '-' && c != ':').collect();
+
+        let piece_type = match chars.first() {
+            Some('N') => PieceType::Knight,
+            Some('B') => PieceType::Bishop,
+            Some('R') => PieceType::Rook,
+            Some('Q') => PieceType::Queen,
+            Some('K') => PieceType::King,
+            _ => PieceType::Pawn,
+        };
+        if piece_type != PieceType::Pawn {
+            chars.remove(0);
+        }
+
+        // A pawn move otherwise ends in a rank, so a trailing letter is a promotion, written e8=Q or e8Q
+        let mut promotion = None;
+        if piece_type == PieceType::Pawn && chars.last().is_some_and(|c| c.is_ascii_alphabetic()) {
+            promotion = match chars.pop().map(|c| c.to_ascii_uppercase()) {
+                Some('N') => Some(PieceType::Knight),
+                Some('B') => Some(PieceType::Bishop),
+                Some('R') => Some(PieceType::Rook),
+                Some('Q') => Some(PieceType::Queen),
+                _ => return Err(syntax_error()),
+            };
+            if chars.last() == Some(&'=') {
+                chars.pop();
+            }
+        }
+
+        if chars.len() < 2 || chars.len() > 4 {
+            return Err(syntax_error());
+        }
+        let destination: String = chars[chars.len() - 2..].iter().collect();
+        let to = parse_square(&destination).ok_or_else(syntax_error)?;
+
+        // Whatever is left in front of the destination narrows down the starting square
+        let mut from_file = None;
+        let mut from_rank = None;
+        for &c in &chars[..chars.len() - 2] {
+            match c {
+                'a'..='h' if from_file.is_none() => from_file = Some(c as u8 - b'a'),
+                '1'..='8' if from_rank.is_none() => from_rank = Some(c as u8 - b'1'),
+                _ => return Err(syntax_error()),
+            }
+        }
+
+        // A pawn move that names no starting file is a push, straight up the destination file.
+        // Without this "d5" would also match a capture such as exd5.
+        if piece_type == PieceType::Pawn && from_file.is_none() {
+            from_file = Some(get_file(to));
+        }
+
+        let mut candidates = legal_moves.into_iter().filter(|mv| {
+            mv.piece_type == piece_type
+                && mv.to == to
+                && mv.promotion == promotion
+                && from_file.is_none_or(|file| get_file(mv.from) == file)
+                && from_rank.is_none_or(|rank| get_rank(mv.from) == rank)
+        });
+
+        match (candidates.next(), candidates.next()) {
+            (Some(mv), None) => Ok(mv),
+            (Some(_), Some(_)) => Err(SanError::Ambiguous(san.to_string())),
+            (None, _) => Err(SanError::Illegal(san.to_string())),
+        }
+    }
+
+    // The file, rank or square needed to tell a piece move apart from the same kind of piece
+    // moving to the same square
+    fn disambiguation(&mut self, mv: Move) -> String {
+        let others: Vec<Move> = self
+            .generate_legal_moves()
+            .into_iter()
+            .filter(|other| other.piece_type == mv.piece_type && other.to == mv.to && other.from != mv.from)
+            .collect();
+
+        if others.is_empty() {
+            return String::new();
+        }
+
+        let file = (b'a' + get_file(mv.from)) as char;
+        let rank = (b'1' + get_rank(mv.from)) as char;
+        if others.iter().all(|other| get_file(other.from) != get_file(mv.from)) {
+            file.to_string()
+        } else if others.iter().all(|other| get_rank(other.from) != get_rank(mv.from)) {
+            rank.to_string()
+        } else {
+            square_name(mv.from)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn san(fen: &str, uci: &str) -> String {
+        let mut board = Board::from_fen(fen).unwrap();
+        let mv = board.generate_legal_moves().into_iter().find(|mv| mv.get_uci() == uci).unwrap();
+        board.move_to_san(mv)
+    }
+
+    fn parse(fen: &str, san: &str) -> Result<String, SanError> {
+        Board::from_fen(fen).unwrap().parse_san(san).map(|mv| mv.get_uci())
+    }
+
+    #[test]
+    fn disambiguates_by_file_rank_or_square() {
+        // Knights on b1 and f3 can both reach d2
+        assert_eq!(san("4k3/8/8/8/8/5N2/8/1N2K3 w - - 0 1", "b1d2"), "Nbd2");
+        // Rooks on a1 and a5 can both reach a3
+        assert_eq!(san("4k3/8/8/R7/8/8/8/R3K3 w - - 0 1", "a1a3"), "R1a3");
+        // Queens on e4, h4 and h1 can all reach e1; neither file nor rank alone names h4
+        assert_eq!(san("1k6/8/8/8/4Q2Q/8/8/3K3Q w - - 0 1", "h4e1"), "Qh4e1");
+        assert_eq!(san("1k6/8/8/8/4Q2Q/8/8/3K3Q w - - 0 1", "e4e1"), "Qee1");
+    }
+
+    #[test]
+    fn marks_check_and_mate() {
+        assert_eq!(san("4k3/8/8/8/8/8/8/R3K3 w - - 0 1", "a1a8"), "Ra8+");
+        assert_eq!(san("6k1/5ppp/8/8/8/8/8/R3K3 w - - 0 1", "a1a8"), "Ra8#");
+    }
+
+    #[test]
+    fn writes_castling() {
+        assert_eq!(san("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1", "e1g1"), "O-O");
+        assert_eq!(san("r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1", "e8c8"), "O-O-O");
+    }
+
+    #[test]
+    fn writes_promotions() {
+        assert_eq!(san("3r3k/2P5/8/8/8/8/8/4K3 w - - 0 1", "c7c8q"), "c8=Q");
+        assert_eq!(san("7k/2P5/8/8/8/8/8/4K3 w - - 0 1", "c7c8q"), "c8=Q+");
+        assert_eq!(san("3r3k/2P5/8/8/8/8/8/4K3 w - - 0 1", "c7d8n"), "cxd8=N");
+    }
+
+    #[test]
+    fn parses_every_move_it_writes() {
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        let mut board = Board::from_fen(fen).unwrap();
+        for mv in board.generate_legal_moves() {
+            let san = board.move_to_san(mv);
+            assert_eq!(board.parse_san(&san), Ok(mv), "{}", san);
+        }
+    }
+
+    #[test]
+    fn pawn_push_does_not_match_a_capture() {
+        // Both the e4 and the d4 pawn could end up on d5, one by capturing
+        let fen = "4k3/8/8/3p4/4P3/8/3P4/4K3 w - - 0 1";
+        assert_eq!(parse(fen, "exd5"), Ok("e4d5".to_string()));
+        assert_eq!(parse(fen, "ed5"), Ok("e4d5".to_string()));
+        assert_eq!(parse(fen, "d4"), Ok("d2d4".to_string()));
+        assert_eq!(parse(fen, "d5"), Err(SanError::Illegal("d5".to_string())));
+    }
+
+    #[test]
+    fn tolerates_common_variants() {
+        let fen = "r3k2r/1P6/8/8/8/8/8/R3K1NR w KQkq - 0 1";
+        assert_eq!(parse(fen, "0-0-0"), Ok("e1c1".to_string()));
+        assert_eq!(parse(fen, "O-O-O+"), Ok("e1c1".to_string()));
+        assert_eq!(parse(fen, "Ng1-f3"), Ok("g1f3".to_string()));
+        assert_eq!(parse(fen, "Nxf3"), Ok("g1f3".to_string()));
+        assert_eq!(parse(fen, "Nf3!?"), Ok("g1f3".to_string()));
+        assert_eq!(parse(fen, "b8Q"), Ok("b7b8q".to_string()));
+        assert_eq!(parse(fen, "b8=q"), Ok("b7b8q".to_string()));
+        assert_eq!(parse(fen, "bxa8=R"), Ok("b7a8r".to_string()));
+    }
+
+    #[test]
+    fn rejects_bad_moves() {
+        let fen = "4k3/8/8/8/8/5N2/8/1N2K3 w - - 0 1";
+        assert_eq!(parse(fen, ""), Err(SanError::Empty));
+        assert_eq!(parse(fen, "Nd9"), Err(SanError::Syntax("Nd9".to_string())));
+        assert_eq!(parse(fen, "Nd3"), Err(SanError::Illegal("Nd3".to_string())));
+        assert_eq!(parse(fen, "Nd2"), Err(SanError::Ambiguous("Nd2".to_string())));
+        assert_eq!(parse(fen, "O-O"), Err(SanError::Illegal("O-O".to_string())));
+    }
+}
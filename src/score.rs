@@ -0,0 +1,175 @@
+// score.rs
+// Search scores. A score counts centipawns from the point of view of the side to move, except near
+// the ends of the range, where it stands for a forced mate and how many plies away from the root it is.
+// That way a quicker mate always scores higher than a slower one, and a slower loss higher than a quicker one.
+use std::fmt;
+use std::ops::{Add, Neg, Sub};
+
+// Deepest ply the search can reach, quiescence included
+pub const MAX_PLY: usize = 256;
+
+const MATE_VALUE: i32 = 32000;
+
+// Every score further from zero than this is a mate
+const MATE_THRESHOLD: i32 = MATE_VALUE - MAX_PLY as i32;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Score(i32);
+
+// A score the way it is shown to the user
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScoreKind {
+    Centipawns(i32),
+    Mate(i32), // Moves until mate, negative when the side to move is the one getting mated
+}
+
+impl Score {
+    pub const DRAW: Score = Score(0);
+
+    // Beyond every real score, used for the initial search window
+    pub const INFINITE: Score = Score(MATE_VALUE + 1);
+
+    // A centipawn score, kept clear of the mate range
+    pub fn cp(centipawns: i32) -> Self {
+        Score(centipawns.clamp(-MATE_THRESHOLD, MATE_THRESHOLD))
+    }
+
+    // The side to move mates, `ply` plies after the root
+    pub fn mate_in(ply: usize) -> Self {
+        Score(MATE_VALUE - ply as i32)
+    }
+
+    // The side to move is checkmated, `ply` plies after the root
+    pub fn mated_in(ply: usize) -> Self {
+        Score(-MATE_VALUE + ply as i32)
+    }
+
+    pub fn is_mate(self) -> bool {
+        self.0.abs() > MATE_THRESHOLD
+    }
+
+    // The raw value, for comparisons and arithmetic such as margins
+    pub fn value(self) -> i32 {
+        self.0
+    }
+
+    pub fn kind(self) -> ScoreKind {
+        if self.0 > MATE_THRESHOLD {
+            ScoreKind::Mate((MATE_VALUE - self.0 + 1) / 2)
+        } else if self.0 < -MATE_THRESHOLD {
+            ScoreKind::Mate(-(MATE_VALUE + self.0) / 2)
+        } else {
+            ScoreKind::Centipawns(self.0)
+        }
+    }
+
+    // Mate scores are counted from the root, but a transposition table entry can be reached at any
+    // ply, so they are stored counted from the position itself
+    pub fn to_tt(self, ply: usize) -> i32 {
+        if self.0 > MATE_THRESHOLD {
+            self.0 + ply as i32
+        } else if self.0 < -MATE_THRESHOLD {
+            self.0 - ply as i32
+        } else {
+            self.0
+        }
+    }
+
+    // Turns a stored score back into one counted from the root
+    pub fn from_tt(value: i32, ply: usize) -> Self {
+        if value > MATE_THRESHOLD {
+            Score(value - ply as i32)
+        } else if value < -MATE_THRESHOLD {
+            Score(value + ply as i32)
+        } else {
+            Score(value)
+        }
+    }
+}
+
+impl Neg for Score {
+    type Output = Score;
+
+    fn neg(self) -> Score {
+        Score(-self.0)
+    }
+}
+
+impl Add<i32> for Score {
+    type Output = Score;
+
+    fn add(self, centipawns: i32) -> Score {
+        Score(self.0 + centipawns)
+    }
+}
+
+impl Sub<i32> for Score {
+    type Output = Score;
+
+    fn sub(self, centipawns: i32) -> Score {
+        Score(self.0 - centipawns)
+    }
+}
+
+// UCI style: "cp 35" or "mate -3"
+impl fmt::Display for Score {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind() {
+            ScoreKind::Centipawns(centipawns) => write!(f, "cp {}", centipawns),
+            ScoreKind::Mate(moves) => write!(f, "mate {}", moves),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transposition_table_scores_round_trip() {
+        for score in [Score::cp(-250), Score::DRAW, Score::cp(900), Score::mate_in(5), Score::mated_in(8)] {
+            for ply in [0, 1, 7, 40] {
+                assert_eq!(Score::from_tt(score.to_tt(ply), ply), score, "{} at ply {}", score, ply);
+            }
+        }
+    }
+
+    #[test]
+    fn stored_mates_count_from_where_they_are_found_again() {
+        // Mate 5 plies from the root, found at ply 3: 2 plies from that position. Reached again at
+        // ply 7 through another move order, the same mate is 9 plies from the root.
+        let stored = Score::mate_in(5).to_tt(3);
+        assert_eq!(stored, Score::mate_in(2).value());
+        assert_eq!(Score::from_tt(stored, 7), Score::mate_in(9));
+
+        let stored = Score::mated_in(6).to_tt(2);
+        assert_eq!(stored, Score::mated_in(4).value());
+        assert_eq!(Score::from_tt(stored, 5), Score::mated_in(9));
+    }
+
+    #[test]
+    fn mates_are_shown_in_moves_for_either_side() {
+        assert_eq!(Score::mate_in(1).kind(), ScoreKind::Mate(1));
+        assert_eq!(Score::mate_in(3).kind(), ScoreKind::Mate(2));
+        assert_eq!(Score::mate_in(4).kind(), ScoreKind::Mate(2));
+        assert_eq!(Score::mated_in(2).kind(), ScoreKind::Mate(-1));
+        assert_eq!(Score::mated_in(4).kind(), ScoreKind::Mate(-2));
+        assert_eq!(Score::mated_in(6).kind(), ScoreKind::Mate(-3));
+
+        assert_eq!(Score::mate_in(3).to_string(), "mate 2");
+        assert_eq!(Score::mated_in(4).to_string(), "mate -2");
+        assert_eq!(Score::cp(-35).to_string(), "cp -35");
+        assert_eq!((-Score::mate_in(3)).kind(), ScoreKind::Mate(-1));
+    }
+
+    #[test]
+    fn centipawns_stay_out_of_the_mate_range() {
+        for centipawns in [i32::MIN + 1, -MATE_VALUE, -MATE_THRESHOLD - 1, 0, MATE_THRESHOLD + 1, MATE_VALUE, i32::MAX] {
+            let score = Score::cp(centipawns);
+            assert!(!score.is_mate(), "cp {} became a mate", centipawns);
+            assert!(matches!(score.kind(), ScoreKind::Centipawns(_)));
+        }
+        assert!(Score::mate_in(MAX_PLY - 1).is_mate());
+        assert!(Score::mated_in(MAX_PLY - 1).is_mate());
+    }
+}
@@ -0,0 +1,103 @@
+// search.rs
+// Running a search in the background. A front-end, UCI or otherwise, starts the search, keeps
+// handling its own input while the engine thinks, and receives progress as events.
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::engine::{Engine, SearchInfo, SearchLimits};
+use crate::r#move::ScoredMove;
+
+// How often a finished infinite search checks whether it has been told to stop yet
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+#[derive(Clone, Debug)]
+pub enum SearchEvent {
+    Info(SearchInfo),               // An iteration of the search completed
+    Finished(Option<ScoredMove>),   // The search is over; None if the side to move has no legal moves
+}
+
+// A search running on its own thread. Dropping the handle stops the search and waits for it.
+pub struct SearchHandle {
+    stop: Arc<AtomicBool>,           // The engine's stop flag, which aborts the search
+    stop_requested: Arc<AtomicBool>, // Stays set once stop() was called, unlike the engine's flag
+    events: Option<Receiver<SearchEvent>>, // None when the events go to a callback instead
+    thread: Option<JoinHandle<Engine>>,
+}
+
+impl SearchHandle {
+    // Starts searching the engine's position; progress is read from the handle with `try_recv` or `recv`
+    pub fn start(engine: Engine, limits: SearchLimits) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let mut handle = Self::start_with_callback(engine, limits, move |event| {
+            // Nobody listening any more is not a reason to stop searching
+            let _ = sender.send(event);
+        });
+        handle.events = Some(receiver);
+        handle
+    }
+
+    // Starts searching the engine's position, passing every event to `on_event` on the search thread
+    pub fn start_with_callback<F>(mut engine: Engine, limits: SearchLimits, mut on_event: F) -> Self
+    where
+        F: FnMut(SearchEvent) + Send + 'static,
+    {
+        let stop = engine.stop_flag();
+        let stop_requested = Arc::new(AtomicBool::new(false));
+        let thread_stop_requested = Arc::clone(&stop_requested);
+
+        let thread = thread::spawn(move || {
+            let best_move = engine.search(&limits, |info| on_event(SearchEvent::Info(info.clone())));
+
+            // An infinite search only finishes when it is told to, even if it ran out of depth
+            if limits.infinite {
+                while !thread_stop_requested.load(Ordering::Relaxed) {
+                    thread::sleep(STOP_POLL_INTERVAL);
+                }
+            }
+
+            on_event(SearchEvent::Finished(best_move));
+            engine
+        });
+
+        Self { stop, stop_requested, events: None, thread: Some(thread) }
+    }
+
+    // Asks the search to finish as soon as possible. It still reports its best move.
+    pub fn stop(&self) {
+        self.stop_requested.store(true, Ordering::Relaxed);
+        self.stop.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.thread.as_ref().is_none_or(|thread| thread.is_finished())
+    }
+
+    // The next event if there is one already, without blocking
+    pub fn try_recv(&self) -> Option<SearchEvent> {
+        self.events.as_ref()?.try_recv().ok()
+    }
+
+    // Waits for the next event. Returns None once the search has finished and every event was read.
+    pub fn recv(&self) -> Option<SearchEvent> {
+        self.events.as_ref()?.recv().ok()
+    }
+
+    // Waits for the search to finish, which an infinite search only does after stop(), and hands
+    // the engine back, with its transposition table and move ordering tables, for the next search
+    pub fn join(mut self) -> Engine {
+        let thread = self.thread.take().expect("search thread is only taken once");
+        thread.join().expect("search thread panicked")
+    }
+}
+
+impl Drop for SearchHandle {
+    fn drop(&mut self) {
+        if let Some(thread) = self.thread.take() {
+            self.stop();
+            let _ = thread.join();
+        }
+    }
+}
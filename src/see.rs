@@ -0,0 +1,207 @@
+// see.rs
+// Static exchange evaluation: what a capture wins or loses once every piece that can join in
+// has recaptured on the square, each side always using its least valuable piece and free to
+// stop capturing when carrying on would lose material.
+use crate::attacks;
+use crate::board::{Board, BLACK, WHITE};
+use crate::piece::PieceType;
+use crate::r#move::Move;
+use crate::utils::pop_lsb;
+
+// Capturers in the order they are thrown into an exchange
+const CAPTURE_ORDER: [PieceType; 6] = [PieceType::Pawn, PieceType::Knight, PieceType::Bishop, PieceType::Rook, PieceType::Queen, PieceType::King];
+
+// Losing the king loses everything; a king only ever takes last
+const KING_VALUE: i32 = 20000;
+
+// An exchange can't be longer than the number of pieces on the board
+const MAX_EXCHANGE: usize = 32;
+
+fn see_value(piece_type: PieceType) -> i32 {
+    if piece_type == PieceType::King { KING_VALUE } else { piece_type.value() }
+}
+
+impl Board {
+    // Every piece of either side that attacks `square`, with `occupancy` deciding what blocks the sliders
+    pub fn attackers_to(&self, square: u8, occupancy: u64) -> u64 {
+        let bishops_and_queens = self.white_bishops | self.black_bishops | self.white_queens | self.black_queens;
+        let rooks_and_queens = self.white_rooks | self.black_rooks | self.white_queens | self.black_queens;
+
+        // A pawn of one side attacks the square if a pawn of the other side there would attack it
+        (attacks::pawn_attacks(square, BLACK) & self.white_pawns)
+            | (attacks::pawn_attacks(square, WHITE) & self.black_pawns)
+            | (attacks::knight_attacks(square) & (self.white_knights | self.black_knights))
+            | (attacks::king_attacks(square) & (self.white_king | self.black_king))
+            | (attacks::bishop_attacks(square, occupancy) & bishops_and_queens)
+            | (attacks::rook_attacks(square, occupancy) & rooks_and_queens)
+    }
+
+    // Material won by a move once the exchange it starts on the target square has played out,
+    // in centipawns for the side making the move. Quiet moves count the piece they put en prise.
+    pub fn see(&self, mv: Move) -> i32 {
+        // Castling captures nothing and puts nothing en prise
+        if mv.piece_type == PieceType::King && mv.from.abs_diff(mv.to) == 2 {
+            return 0;
+        }
+
+        let Some((_, mover)) = self.piece_at(mv.from) else {
+            return 0;
+        };
+
+        let mut occupancy = self.occupancy() ^ (1u64 << mv.from);
+        let mut gain = [0i32; MAX_EXCHANGE];
+
+        let is_en_passant = mv.piece_type == PieceType::Pawn && self.en_passant == Some(mv.to) && self.piece_at(mv.to).is_none();
+        if is_en_passant {
+            let captured_square = if mover == WHITE { mv.to - 8 } else { mv.to + 8 };
+            occupancy ^= 1u64 << captured_square;
+            gain[0] = PieceType::Pawn.value();
+        } else if let Some((captured, _)) = self.piece_at(mv.to) {
+            gain[0] = see_value(captured);
+        }
+
+        // The piece now standing on the square, the next thing to be captured
+        let mut on_square = see_value(mv.piece_type);
+        if let Some(promotion) = mv.promotion {
+            gain[0] += promotion.value() - PieceType::Pawn.value();
+            on_square = promotion.value();
+        }
+
+        let mut attackers = self.attackers_to(mv.to, occupancy) & occupancy;
+        let mut side = !mover;
+        let mut depth = 0;
+
+        while depth + 1 < MAX_EXCHANGE {
+            let side_attackers = attackers & self.side_occupancy(side);
+            let Some((piece_type, bitboard)) = CAPTURE_ORDER
+                .iter()
+                .map(|&piece_type| (piece_type, side_attackers & self.pieces(piece_type, side)))
+                .find(|&(_, bitboard)| bitboard != 0)
+            else {
+                break;
+            };
+
+            depth += 1;
+            gain[depth] = on_square - gain[depth - 1];
+
+            on_square = see_value(piece_type);
+            let mut capturer = bitboard;
+            occupancy ^= 1u64 << pop_lsb(&mut capturer);
+
+            // Moving a piece off the line can uncover a slider behind it
+            if matches!(piece_type, PieceType::Pawn | PieceType::Bishop | PieceType::Queen) {
+                attackers |= attacks::bishop_attacks(mv.to, occupancy)
+                    & (self.white_bishops | self.black_bishops | self.white_queens | self.black_queens);
+            }
+            if matches!(piece_type, PieceType::Rook | PieceType::Queen) {
+                attackers |= attacks::rook_attacks(mv.to, occupancy)
+                    & (self.white_rooks | self.black_rooks | self.white_queens | self.black_queens);
+            }
+            attackers &= occupancy;
+
+            side = !side;
+        }
+
+        // Walk back up the exchange: at every step the side to capture may also decline
+        while depth > 0 {
+            gain[depth - 1] = -(-gain[depth - 1]).max(gain[depth]);
+            depth -= 1;
+        }
+
+        gain[0]
+    }
+
+    // Whether a move wins at least `threshold` centipawns in the exchange it starts
+    pub fn see_ge(&self, mv: Move, threshold: i32) -> bool {
+        self.see(mv) >= threshold
+    }
+
+    // Pieces of `side` that the opponent could win material by capturing
+    pub fn hanging_pieces(&self, side: bool) -> u64 {
+        let occupancy = self.occupancy();
+        let mut hanging = 0;
+
+        let mut pieces = self.side_occupancy(side) & !self.pieces(PieceType::King, side);
+        while pieces != 0 {
+            let square = pop_lsb(&mut pieces);
+            let enemy_attackers = self.attackers_to(square, occupancy) & self.side_occupancy(!side);
+
+            // Try the capture with the least valuable attacker
+            let attacker = CAPTURE_ORDER
+                .iter()
+                .map(|&piece_type| (piece_type, enemy_attackers & self.pieces(piece_type, !side)))
+                .find(|&(_, bitboard)| bitboard != 0);
+
+            if let Some((piece_type, bitboard)) = attacker {
+                let from = bitboard.trailing_zeros() as u8;
+                let last_rank = if side == WHITE { 0 } else { 7 };
+                let promotion = (piece_type == PieceType::Pawn && square / 8 == last_rank).then_some(PieceType::Queen);
+                if self.see(Move::new(from, square, piece_type, promotion)) > 0 {
+                    hanging |= 1u64 << square;
+                }
+            }
+        }
+
+        hanging
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::piece::PieceType::{Knight, Pawn, Queen, Rook};
+
+    fn see(fen: &str, uci: &str) -> i32 {
+        let board = Board::from_fen(fen).unwrap();
+        let mv = board.clone().generate_legal_moves().into_iter().find(|mv| mv.get_uci() == uci).unwrap();
+        board.see(mv)
+    }
+
+    #[test]
+    fn undefended_capture_wins_the_piece() {
+        assert_eq!(see("4k3/8/8/3n4/8/8/8/3RK3 w - - 0 1", "d1d5"), Knight.value());
+    }
+
+    #[test]
+    fn defended_capture_trades() {
+        // RxN, RxR: the knight for the rook
+        assert_eq!(see("3rk3/8/8/3n4/8/8/8/3RK3 w - - 0 1", "d1d5"), Knight.value() - Rook.value());
+        // PxN, PxP: a knight for a pawn
+        assert_eq!(see("4k3/8/4p3/3n4/2P5/8/8/4K3 w - - 0 1", "c4d5"), Knight.value() - Pawn.value());
+    }
+
+    #[test]
+    fn queen_takes_pawn_defended_by_pawn() {
+        assert_eq!(see("4k3/8/4p3/3p4/8/8/8/3QK3 w - - 0 1", "d1d5"), Pawn.value() - Queen.value());
+    }
+
+    #[test]
+    fn xray_attackers_join_in() {
+        // The rook on e1 recaptures through the one on e2 once it has gone
+        assert_eq!(see("4r1k1/8/8/4p3/8/8/4R3/4R1K1 w - - 0 1", "e2e5"), Pawn.value());
+        // Without it the pawn costs the rook
+        assert_eq!(see("4r1k1/8/8/4p3/8/8/4R3/6K1 w - - 0 1", "e2e5"), Pawn.value() - Rook.value());
+        // The defending queen behind the rook keeps the exchange going the other way
+        assert_eq!(see("4q1k1/4r3/8/4p3/8/8/4R3/4R1K1 w - - 0 1", "e2e5"), Pawn.value() - Rook.value());
+    }
+
+    #[test]
+    fn quiet_move_counts_what_it_puts_en_prise() {
+        assert_eq!(see("4k3/8/8/2p5/8/8/8/3QK3 w - - 0 1", "d1d4"), -Queen.value());
+        assert_eq!(see("4k3/8/8/8/8/8/8/3QK3 w - - 0 1", "d1d4"), 0);
+    }
+
+    #[test]
+    fn promotion_capture_counts_the_new_piece() {
+        assert_eq!(see("r3k3/1P6/8/8/8/8/8/4K3 w - - 0 1", "b7a8q"), Rook.value() + Queen.value() - Pawn.value());
+        // The king takes the new queen back
+        assert_eq!(see("rk6/1P6/8/8/8/8/8/4K3 w - - 0 1", "b7a8q"), Rook.value() - Pawn.value());
+        assert_eq!(see("rk6/1P6/8/8/8/8/8/4K3 w - - 0 1", "b7a8n"), Rook.value() + Knight.value() - Pawn.value() - Knight.value());
+    }
+
+    #[test]
+    fn en_passant_captures_a_pawn() {
+        assert_eq!(see("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", "e5d6"), Pawn.value());
+        assert_eq!(see("4k3/2p5/8/3pP3/8/8/8/4K3 w - d6 0 1", "e5d6"), 0);
+    }
+}
@@ -0,0 +1,175 @@
+// time.rs
+// Deciding how long to think. The search gets two limits from the clock: a soft one, after which
+// no new iteration is started, and a hard one, at which a running iteration is aborted. The soft
+// limit grows while the best move keeps changing between iterations and shrinks back once it settles.
+use std::time::{Duration, Instant};
+
+use crate::board::WHITE;
+use crate::engine::SearchLimits;
+
+// Assumed number of moves left in the game when the GUI doesn't say
+const DEFAULT_MOVES_TO_GO: u64 = 30;
+
+// Time kept back for communication overhead, so we never lose on time, in milliseconds
+const MOVE_OVERHEAD: u64 = 50;
+
+// How far past the soft limit the hard limit lies
+const HARD_LIMIT_FACTOR: u32 = 3;
+
+// Bounds for how much an unstable best move can stretch the soft limit
+const MAX_INSTABILITY_SCALE: f64 = 2.5;
+const INSTABILITY_GROWTH: f64 = 1.5;
+const STABILITY_DECAY: f64 = 0.85;
+
+#[derive(Clone, Debug)]
+pub struct TimeManager {
+    start: Instant,
+    soft_limit: Option<Duration>,
+    hard_limit: Option<Duration>,
+    instability_scale: f64, // Multiplies the soft limit, 1.0 while the best move is stable
+}
+
+impl Default for TimeManager {
+    fn default() -> Self {
+        Self::unlimited()
+    }
+}
+
+impl TimeManager {
+    // A search that only ends on its depth or node limit, or when told to stop
+    pub fn unlimited() -> Self {
+        Self {
+            start: Instant::now(),
+            soft_limit: None,
+            hard_limit: None,
+            instability_scale: 1.0,
+        }
+    }
+
+    // Limits for the side to move, starting the clock now
+    pub fn new(limits: &SearchLimits, side: bool) -> Self {
+        let mut manager = Self::unlimited();
+        if limits.infinite {
+            return manager;
+        }
+
+        // A fixed time per move is used in full, there is no point stopping early. The overhead
+        // still comes off it, or the move would arrive late; at least 1 ms is always left to search.
+        if let Some(movetime) = limits.movetime {
+            let movetime = Duration::from_millis(movetime.saturating_sub(MOVE_OVERHEAD).max(1));
+            manager.soft_limit = Some(movetime);
+            manager.hard_limit = Some(movetime);
+            return manager;
+        }
+
+        let (time, increment) = if side == WHITE { (limits.wtime, limits.winc) } else { (limits.btime, limits.binc) };
+        let Some(time) = time else {
+            return manager;
+        };
+        let increment = increment.unwrap_or(0);
+        let moves_to_go = limits.movestogo.unwrap_or(DEFAULT_MOVES_TO_GO).max(1);
+
+        // Never plan to use more than what is left on the clock minus the overhead
+        let available = time.saturating_sub(MOVE_OVERHEAD.min(time / 2));
+
+        // An even share of the remaining time plus most of the increment
+        let soft = (time / moves_to_go + increment * 3 / 4).min(available);
+        let hard = (soft * HARD_LIMIT_FACTOR as u64).min(available);
+
+        manager.soft_limit = Some(Duration::from_millis(soft));
+        manager.hard_limit = Some(Duration::from_millis(hard));
+        manager
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    // When a running search has to be aborted, if ever
+    pub fn hard_deadline(&self) -> Option<Instant> {
+        self.hard_limit.map(|limit| self.start + limit)
+    }
+
+    // The soft limit after the instability scaling, never beyond the hard limit
+    pub fn soft_limit(&self) -> Option<Duration> {
+        let scaled = self.soft_limit?.mul_f64(self.instability_scale);
+        Some(self.hard_limit.map_or(scaled, |hard| scaled.min(hard)))
+    }
+
+    // Called after every completed iteration with whether it changed the best move
+    pub fn update(&mut self, best_move_changed: bool) {
+        self.instability_scale = if best_move_changed {
+            (self.instability_scale * INSTABILITY_GROWTH).min(MAX_INSTABILITY_SCALE)
+        } else {
+            (self.instability_scale * STABILITY_DECAY).max(1.0)
+        };
+    }
+
+    // Whether there is time to start another iteration
+    pub fn can_start_iteration(&self) -> bool {
+        self.soft_limit().is_none_or(|limit| self.elapsed() < limit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::BLACK;
+
+    fn limits(limits: SearchLimits) -> (Option<Duration>, Option<Duration>) {
+        let manager = TimeManager::new(&limits, WHITE);
+        (manager.soft_limit(), manager.hard_limit)
+    }
+
+    fn millis(ms: u64) -> Option<Duration> {
+        Some(Duration::from_millis(ms))
+    }
+
+    #[test]
+    fn movetime_keeps_back_the_overhead() {
+        assert_eq!(limits(SearchLimits { movetime: Some(1000), ..SearchLimits::default() }), (millis(950), millis(950)));
+        assert_eq!(limits(SearchLimits { movetime: Some(MOVE_OVERHEAD), ..SearchLimits::default() }), (millis(1), millis(1)));
+        assert_eq!(limits(SearchLimits { movetime: Some(0), ..SearchLimits::default() }), (millis(1), millis(1)));
+    }
+
+    #[test]
+    fn clock_is_shared_over_the_moves_to_go() {
+        let clock = SearchLimits {
+            wtime: Some(60_000),
+            btime: Some(100),
+            winc: Some(1_000),
+            binc: Some(2_000),
+            movestogo: Some(20),
+            ..SearchLimits::default()
+        };
+        assert_eq!(limits(clock.clone()), (millis(3_750), millis(11_250)));
+
+        // Black's clock is nearly out: however big the increment, never more than what is left minus the overhead
+        let manager = TimeManager::new(&clock, BLACK);
+        assert_eq!((manager.soft_limit(), manager.hard_limit), (millis(50), millis(50)));
+    }
+
+    #[test]
+    fn no_limits_without_a_clock() {
+        assert_eq!(limits(SearchLimits::depth(10)), (None, None));
+        assert_eq!(limits(SearchLimits { wtime: Some(1_000), infinite: true, ..SearchLimits::default() }), (None, None));
+    }
+
+    #[test]
+    fn unstable_best_move_stretches_the_soft_limit() {
+        let mut manager = TimeManager::new(&SearchLimits { wtime: Some(30_000), ..SearchLimits::default() }, WHITE);
+        let soft = manager.soft_limit().unwrap();
+
+        manager.update(true);
+        assert!(manager.soft_limit().unwrap() > soft);
+        for _ in 0..10 {
+            manager.update(true);
+        }
+        assert!(manager.soft_limit() <= manager.hard_limit);
+
+        for _ in 0..20 {
+            manager.update(false);
+        }
+        assert_eq!(manager.soft_limit().unwrap(), soft);
+    }
+}
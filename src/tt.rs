@@ -0,0 +1,302 @@
+// tt.rs
+// Transposition table shared by every thread searching a position.
+//
+// Each slot is two atomics: the packed entry data and the position key xored with that data.
+// A reader only accepts a slot if key ^ data gives back its own key, so an entry torn by two
+// threads writing at the same time is simply treated as a miss, no locking required.
+use std::fmt;
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+
+use crate::piece::PieceType;
+use crate::r#move::Move;
+
+pub const DEFAULT_HASH_MB: usize = 16;
+pub const MAX_HASH_MB: usize = 1024;
+
+// Generations wrap around after this many searches
+const AGE_MASK: u8 = 0xF;
+
+// How the stored score relates to the true score of the position
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Bound {
+    Exact, // The score is exact
+    Lower, // The search failed high, the true score is at least this
+    Upper, // The search failed low, the true score is at most this
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TtEntry {
+    pub depth: u8,
+    pub bound: Bound,
+    pub score: i32,
+    pub best_move: Option<Move>,
+}
+
+struct Slot {
+    key: AtomicU64,  // Position key xored with data
+    data: AtomicU64, // Packed entry, zero when the slot is empty
+}
+
+pub struct TranspositionTable {
+    slots: Vec<Slot>,
+    age: AtomicU8, // Bumped at the start of every search so old entries get replaced first
+}
+
+impl fmt::Debug for TranspositionTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TranspositionTable")
+            .field("slots", &self.slots.len())
+            .field("age", &self.age.load(Ordering::Relaxed))
+            .finish()
+    }
+}
+
+impl Default for TranspositionTable {
+    fn default() -> Self {
+        Self::new(DEFAULT_HASH_MB)
+    }
+}
+
+impl TranspositionTable {
+    // Creates a table using roughly `size_mb` megabytes
+    pub fn new(size_mb: usize) -> Self {
+        let size_mb = size_mb.clamp(1, MAX_HASH_MB);
+        let slot_count = size_mb * 1024 * 1024 / std::mem::size_of::<Slot>();
+
+        Self {
+            slots: (0..slot_count).map(|_| Slot { key: AtomicU64::new(0), data: AtomicU64::new(0) }).collect(),
+            age: AtomicU8::new(0),
+        }
+    }
+
+    // Empties the table, e.g. between games
+    pub fn clear(&self) {
+        for slot in &self.slots {
+            slot.key.store(0, Ordering::Relaxed);
+            slot.data.store(0, Ordering::Relaxed);
+        }
+        self.age.store(0, Ordering::Relaxed);
+    }
+
+    // Starts a new generation; entries from earlier searches become preferred victims for replacement
+    pub fn new_search(&self) {
+        let age = self.age.load(Ordering::Relaxed);
+        self.age.store((age + 1) & AGE_MASK, Ordering::Relaxed);
+    }
+
+    pub fn probe(&self, key: u64) -> Option<TtEntry> {
+        let slot = &self.slots[self.index(key)];
+        let data = slot.data.load(Ordering::Relaxed);
+        if data == 0 || slot.key.load(Ordering::Relaxed) ^ data != key {
+            return None;
+        }
+
+        unpack(data)
+    }
+
+    // Stores an entry. An existing entry for another position is only replaced if it is from
+    // an older search or was searched less deeply.
+    pub fn store(&self, key: u64, depth: usize, bound: Bound, score: i32, best_move: Option<Move>) {
+        let slot = &self.slots[self.index(key)];
+        let age = self.age.load(Ordering::Relaxed);
+        let depth = depth.min(u8::MAX as usize) as u8;
+
+        let old_data = slot.data.load(Ordering::Relaxed);
+        let old_key = slot.key.load(Ordering::Relaxed) ^ old_data;
+        let mut best_move = best_move;
+
+        if old_data != 0 {
+            let old_depth = ((old_data >> 32) & 0xFF) as u8;
+            let old_age = ((old_data >> 42) & 0xF) as u8;
+
+            if old_key == key {
+                // Keep the move we already know about rather than forgetting it
+                if best_move.is_none() {
+                    best_move = unpack_move(old_data >> 46);
+                }
+            } else if old_age == age && old_depth > depth {
+                return;
+            }
+        }
+
+        let data = pack(depth, bound, score, age, best_move);
+        slot.data.store(data, Ordering::Relaxed);
+        slot.key.store(key ^ data, Ordering::Relaxed);
+    }
+
+    // How full the table is, in permille, judged from a sample of slots written during this search
+    pub fn hashfull(&self) -> usize {
+        let age = self.age.load(Ordering::Relaxed);
+        let sample = self.slots.len().min(1000);
+        let used = self.slots[..sample]
+            .iter()
+            .filter(|slot| {
+                let data = slot.data.load(Ordering::Relaxed);
+                data != 0 && ((data >> 42) & 0xF) as u8 == age
+            })
+            .count();
+
+        used * 1000 / sample
+    }
+
+    fn index(&self, key: u64) -> usize {
+        // Maps the key onto the table without needing a power-of-two size
+        ((key as u128 * self.slots.len() as u128) >> 64) as usize
+    }
+}
+
+// Packed layout: score (32 bits) | depth (8) | bound (2) | age (4) | move (18)
+fn pack(depth: u8, bound: Bound, score: i32, age: u8, best_move: Option<Move>) -> u64 {
+    let bound_bits: u64 = match bound {
+        Bound::Exact => 1,
+        Bound::Lower => 2,
+        Bound::Upper => 3,
+    };
+
+    (score as u32 as u64)
+        | (depth as u64) << 32
+        | bound_bits << 40
+        | (age as u64 & 0xF) << 42
+        | pack_move(best_move) << 46
+}
+
+fn unpack(data: u64) -> Option<TtEntry> {
+    let bound = match (data >> 40) & 0b11 {
+        1 => Bound::Exact,
+        2 => Bound::Lower,
+        3 => Bound::Upper,
+        _ => return None,
+    };
+
+    Some(TtEntry {
+        depth: ((data >> 32) & 0xFF) as u8,
+        bound,
+        score: data as u32 as i32,
+        best_move: unpack_move(data >> 46),
+    })
+}
+
+// from (6 bits) | to (6) | piece type (3) | promotion (3), with zero meaning no move
+fn pack_move(mv: Option<Move>) -> u64 {
+    let Some(mv) = mv else {
+        return 0;
+    };
+
+    let promotion = match mv.promotion {
+        None => 0,
+        Some(piece_type) => piece_type.index() as u64 + 1,
+    };
+
+    mv.from as u64 | (mv.to as u64) << 6 | (mv.piece_type.index() as u64) << 12 | promotion << 15
+}
+
+fn unpack_move(bits: u64) -> Option<Move> {
+    let bits = bits & 0x3FFFF;
+    if bits == 0 {
+        return None;
+    }
+
+    let from = (bits & 0x3F) as u8;
+    let to = ((bits >> 6) & 0x3F) as u8;
+    let piece_type = piece_from_index((bits >> 12) & 0b111);
+    let promotion = match (bits >> 15) & 0b111 {
+        0 => None,
+        index => Some(piece_from_index(index - 1)),
+    };
+
+    Some(Move::new(from, to, piece_type, promotion))
+}
+
+fn piece_from_index(index: u64) -> PieceType {
+    match index {
+        0 => PieceType::Pawn,
+        1 => PieceType::Knight,
+        2 => PieceType::Bishop,
+        3 => PieceType::Rook,
+        4 => PieceType::Queen,
+        _ => PieceType::King,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::score::Score;
+
+    // Keys that differ only in their low bits share a slot
+    const KEY: u64 = 0x1234_5678_9ABC_0001;
+    const SAME_SLOT: u64 = 0x1234_5678_9ABC_0002;
+
+    #[test]
+    fn entries_pack_and_unpack() {
+        let moves = [
+            None,
+            Some(Move::new(12, 28, PieceType::Pawn, None)),
+            Some(Move::new(4, 6, PieceType::King, None)),
+            Some(Move::new(52, 61, PieceType::Pawn, Some(PieceType::Queen))),
+            Some(Move::new(11, 2, PieceType::Pawn, Some(PieceType::Knight))),
+        ];
+        let scores = [0, 35, -1200, Score::mate_in(3).value(), Score::mated_in(12).value()];
+
+        for best_move in moves {
+            for score in scores {
+                for (depth, bound) in [(0, Bound::Exact), (7, Bound::Lower), (u8::MAX, Bound::Upper)] {
+                    let entry = unpack(pack(depth, bound, score, AGE_MASK, best_move)).unwrap();
+                    assert_eq!(entry, TtEntry { depth, bound, score, best_move });
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn keeps_deeper_entries_of_the_same_search() {
+        let tt = TranspositionTable::new(1);
+        tt.store(KEY, 8, Bound::Exact, 50, None);
+        tt.store(SAME_SLOT, 3, Bound::Lower, -20, None);
+        assert_eq!(tt.probe(KEY).map(|entry| entry.depth), Some(8));
+        assert_eq!(tt.probe(SAME_SLOT), None);
+
+        // The same position is always updated, however deep it was searched
+        tt.store(KEY, 2, Bound::Upper, 10, None);
+        assert_eq!(tt.probe(KEY).map(|entry| entry.depth), Some(2));
+    }
+
+    #[test]
+    fn replaces_entries_of_older_searches() {
+        let tt = TranspositionTable::new(1);
+        tt.store(KEY, 8, Bound::Exact, 50, None);
+        tt.new_search();
+        tt.store(SAME_SLOT, 3, Bound::Lower, -20, None);
+        assert_eq!(tt.probe(KEY), None);
+        assert_eq!(tt.probe(SAME_SLOT).map(|entry| entry.depth), Some(3));
+    }
+
+    #[test]
+    fn keeps_the_move_when_stored_again_without_one() {
+        let tt = TranspositionTable::new(1);
+        let mv = Move::new(6, 21, PieceType::Knight, None);
+        tt.store(KEY, 4, Bound::Lower, 30, Some(mv));
+        tt.store(KEY, 5, Bound::Upper, -10, None);
+
+        let entry = tt.probe(KEY).unwrap();
+        assert_eq!((entry.depth, entry.bound, entry.score, entry.best_move), (5, Bound::Upper, -10, Some(mv)));
+    }
+
+    #[test]
+    fn clear_empties_the_table() {
+        let tt = TranspositionTable::new(1);
+        assert_eq!(tt.hashfull(), 0);
+
+        // Spread the keys over the whole table, so the sampled slots fill up too
+        let step = u64::MAX / 4096;
+        for index in 0..4096 {
+            tt.store(index * step + 1, 1, Bound::Exact, 0, None);
+        }
+        assert!(tt.hashfull() > 0);
+
+        tt.clear();
+        assert_eq!(tt.hashfull(), 0);
+        assert_eq!(tt.probe(KEY), None);
+        assert_eq!(tt.probe(step + 1), None);
+    }
+}
@@ -0,0 +1,370 @@
+// uci.rs
+// Universal Chess Interface front-end, so the engine can be driven by GUIs and match runners
+use std::io::{self, BufRead};
+use std::sync::Arc;
+
+use crate::board::Board;
+use crate::engine::{Engine, SearchConfig, SearchInfo, SearchLimits, MAX_THREADS};
+use crate::evaluator::ClassicalEvaluator;
+use crate::search::{SearchEvent, SearchHandle};
+use crate::tt::{TranspositionTable, DEFAULT_HASH_MB, MAX_HASH_MB};
+
+const ENGINE_NAME: &str = "Rusty Engine";
+const ENGINE_AUTHOR: &str = "Caleb Wetherell";
+
+const START_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+pub struct Uci {
+    board: Board,
+    search: Option<SearchHandle>,
+    tt: Arc<TranspositionTable>, // Kept between searches so each move benefits from the last
+    threads: usize,
+    config: SearchConfig,
+}
+
+impl Default for Uci {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Uci {
+    pub fn new() -> Self {
+        Self {
+            board: Board::new(),
+            search: None,
+            tt: Arc::new(TranspositionTable::new(DEFAULT_HASH_MB)),
+            threads: 1,
+            config: SearchConfig::default(),
+        }
+    }
+
+    // Reads commands from stdin until "quit" or the end of input.
+    // Searches run on their own thread so "stop" and "isready" are answered while thinking.
+    pub fn run(&mut self) {
+        let stdin = io::stdin();
+        for line in stdin.lock().lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            if !self.handle_command(&line) {
+                break;
+            }
+        }
+        self.stop_search();
+    }
+
+    // Handles a single command line. Returns false when the engine should exit.
+    pub fn handle_command(&mut self, line: &str) -> bool {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let Some((&command, args)) = tokens.split_first() else {
+            return true;
+        };
+
+        match command {
+            "uci" => {
+                println!("id name {}", ENGINE_NAME);
+                println!("id author {}", ENGINE_AUTHOR);
+                println!("option name Hash type spin default {} min 1 max {}", DEFAULT_HASH_MB, MAX_HASH_MB);
+                println!("option name Threads type spin default 1 min 1 max {}", MAX_THREADS);
+                // Switches for the selective search, to measure each part in self-play
+                let config = SearchConfig::default();
+                println!("option name NullMove type check default {}", config.null_move);
+                println!("option name LateMoveReductions type check default {}", config.late_move_reductions);
+                println!("option name ReverseFutility type check default {}", config.reverse_futility);
+                println!("option name Futility type check default {}", config.futility);
+                println!("option name CheckExtensions type check default {}", config.check_extensions);
+                println!("uciok");
+            },
+            "isready" => println!("readyok"),
+            // Not part of UCI: prints the evaluation of the current position, term by term
+            "eval" => println!("{}", ClassicalEvaluator::new().trace(&self.board)),
+            "ucinewgame" => {
+                self.stop_search();
+                self.board = Board::new();
+                self.tt.clear();
+            },
+            "position" => {
+                self.stop_search();
+                self.set_position(args);
+            },
+            "go" => self.go(args),
+            "stop" => self.stop_search(),
+            "setoption" => self.set_option(args),
+            "quit" => {
+                self.stop_search();
+                return false;
+            },
+            // Commands we accept but have nothing to do for
+            "debug" | "register" | "ponderhit" => {},
+            _ => println!("info string unknown command {}", command),
+        }
+
+        true
+    }
+
+    // position [startpos | fen <fen>] [moves <move1> ... <moveN>]
+    fn set_position(&mut self, args: &[&str]) {
+        let moves_index = args.iter().position(|&token| token == "moves").unwrap_or(args.len());
+        let (position, moves) = args.split_at(moves_index);
+
+        let fen = match position.split_first() {
+            Some((&"startpos", _)) => START_FEN.to_string(),
+            Some((&"fen", fields)) => fields.join(" "),
+            _ => {
+                println!("info string expected startpos or fen");
+                return;
+            },
+        };
+
+        let mut board = match Board::from_fen(&fen) {
+            Ok(board) => board,
+            Err(err) => {
+                println!("info string invalid fen: {}", err);
+                return;
+            },
+        };
+
+        // Skip the "moves" token itself
+        for &uci_move in moves.iter().skip(1) {
+            match board.parse_uci_move(uci_move) {
+                Some(mv) => {
+                    board.make_move(mv);
+                },
+                None => {
+                    println!("info string illegal move {}", uci_move);
+                    break;
+                },
+            }
+        }
+
+        self.board = board;
+    }
+
+    // go [depth N] [nodes N] [movetime N] [wtime N] [btime N] [winc N] [binc N] [movestogo N] [infinite]
+    fn go(&mut self, args: &[&str]) {
+        self.stop_search();
+
+        let limits = parse_limits(args);
+        let mut engine = Engine::with_board(Some(self.board.clone()));
+        engine.set_transposition_table(Arc::clone(&self.tt));
+        engine.set_threads(self.threads);
+        engine.set_search_config(self.config);
+
+        // Printed straight from the search thread, so the GUI sees them as soon as they happen.
+        // In infinite mode the search only finishes, and the best move is only sent, after "stop".
+        let handle = SearchHandle::start_with_callback(engine, limits, |event| match event {
+            SearchEvent::Info(info) => println!("{}", format_info(&info)),
+            SearchEvent::Finished(Some(best_move)) => println!("bestmove {}", best_move.mv.get_uci()),
+            SearchEvent::Finished(None) => println!("bestmove 0000"),
+        });
+
+        self.search = Some(handle);
+    }
+
+    // Stops the running search, if any, and waits for it to print its best move
+    fn stop_search(&mut self) {
+        if let Some(search) = self.search.take() {
+            search.stop();
+            search.join();
+        }
+    }
+
+    // setoption name <id> [value <x>]
+    fn set_option(&mut self, args: &[&str]) {
+        let value_index = args.iter().position(|&token| token == "value").unwrap_or(args.len());
+        let name = args[..value_index].iter().skip_while(|&&token| token == "name").copied().collect::<Vec<&str>>().join(" ");
+        let value = args.get(value_index + 1..).map(|tokens| tokens.join(" ")).unwrap_or_default();
+
+        match name.to_lowercase().as_str() {
+            "hash" => match value.parse::<usize>() {
+                Ok(size_mb) => {
+                    self.stop_search();
+                    self.tt = Arc::new(TranspositionTable::new(size_mb));
+                },
+                Err(_) => println!("info string invalid Hash value {}", value),
+            },
+            "threads" => match value.parse::<usize>() {
+                Ok(threads) => self.threads = threads.clamp(1, MAX_THREADS),
+                Err(_) => println!("info string invalid Threads value {}", value),
+            },
+            "nullmove" | "latemovereductions" | "reversefutility" | "futility" | "checkextensions" => {
+                let Ok(enabled) = value.parse::<bool>() else {
+                    println!("info string invalid {} value {}", name, value);
+                    return;
+                };
+                match name.to_lowercase().as_str() {
+                    "nullmove" => self.config.null_move = enabled,
+                    "latemovereductions" => self.config.late_move_reductions = enabled,
+                    "reversefutility" => self.config.reverse_futility = enabled,
+                    "futility" => self.config.futility = enabled,
+                    _ => self.config.check_extensions = enabled,
+                }
+            },
+            _ => println!("info string unknown option {}", name),
+        }
+    }
+}
+
+fn parse_limits(args: &[&str]) -> SearchLimits {
+    let mut limits = SearchLimits::default();
+    let mut tokens = args.iter();
+
+    while let Some(&token) = tokens.next() {
+        match token {
+            "infinite" => limits.infinite = true,
+            "depth" => limits.depth = tokens.next().and_then(|value| value.parse().ok()),
+            "nodes" => limits.nodes = tokens.next().and_then(|value| value.parse().ok()),
+            "movetime" => limits.movetime = tokens.next().and_then(|value| value.parse().ok()),
+            "wtime" => limits.wtime = tokens.next().and_then(|value| parse_time(value)),
+            "btime" => limits.btime = tokens.next().and_then(|value| parse_time(value)),
+            "winc" => limits.winc = tokens.next().and_then(|value| parse_time(value)),
+            "binc" => limits.binc = tokens.next().and_then(|value| parse_time(value)),
+            "movestogo" => limits.movestogo = tokens.next().and_then(|value| value.parse().ok()),
+            _ => {}, // ponder, searchmoves, mate are not supported
+        }
+    }
+
+    limits
+}
+
+// Some GUIs send negative clock times when a player is already flagging
+fn parse_time(value: &str) -> Option<u64> {
+    value.parse::<i64>().ok().map(|time| time.max(0) as u64)
+}
+
+fn format_info(info: &SearchInfo) -> String {
+    let pv: Vec<String> = info.pv.iter().map(|mv| mv.get_uci()).collect();
+
+    format!(
+        "info depth {} seldepth {} score {} nodes {} nps {} hashfull {} time {} pv {}",
+        info.depth, info.seldepth, info.score, info.nodes, info.nps, info.hashfull, info.time.as_millis(), pv.join(" ")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits(command: &str) -> SearchLimits {
+        parse_limits(&command.split_whitespace().collect::<Vec<&str>>())
+    }
+
+    fn uci_after(commands: &[&str]) -> Uci {
+        let mut uci = Uci::new();
+        for command in commands {
+            assert!(uci.handle_command(command));
+        }
+        uci
+    }
+
+    #[test]
+    fn parses_clock_limits() {
+        let limits = limits("wtime 60000 btime 55000 winc 1000 binc 500 movestogo 20");
+        assert_eq!(limits.wtime, Some(60000));
+        assert_eq!(limits.btime, Some(55000));
+        assert_eq!(limits.winc, Some(1000));
+        assert_eq!(limits.binc, Some(500));
+        assert_eq!(limits.movestogo, Some(20));
+        assert_eq!(limits.depth, None);
+        assert!(!limits.infinite);
+    }
+
+    #[test]
+    fn parses_fixed_limits() {
+        let limits = limits("depth 7 nodes 100000 movetime 2500");
+        assert_eq!(limits.depth, Some(7));
+        assert_eq!(limits.nodes, Some(100000));
+        assert_eq!(limits.movetime, Some(2500));
+        assert_eq!(limits.wtime, None);
+
+        assert!(self::limits("infinite").infinite);
+    }
+
+    #[test]
+    fn bad_numbers_leave_the_limit_unset() {
+        let limits = limits("depth x nodes -5 movetime 1.5 wtime -200 movestogo 10");
+        assert_eq!(limits.depth, None);
+        assert_eq!(limits.nodes, None);
+        assert_eq!(limits.movetime, None);
+        // A negative clock is an opponent already flagging, not a bad number
+        assert_eq!(limits.wtime, Some(0));
+        assert_eq!(limits.movestogo, Some(10));
+    }
+
+    #[test]
+    fn sets_up_the_start_position_with_moves() {
+        let uci = uci_after(&["position startpos moves e2e4 e7e5 g1f3"]);
+        assert_eq!(uci.board.to_fen(), "rnbqkbnr/pppp1ppp/8/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2");
+    }
+
+    #[test]
+    fn sets_up_a_fen_with_moves() {
+        let uci = uci_after(&["position fen 4k3/8/8/8/8/8/4P3/4K3 w - - 0 1 moves e2e4 e8d7 e4e5"]);
+        assert_eq!(uci.board.to_fen(), "8/3k4/8/4P3/8/8/8/4K3 b - - 0 2");
+    }
+
+    #[test]
+    fn stops_at_an_illegal_move() {
+        // The moves before the illegal one are played, the rest are not
+        let uci = uci_after(&["position startpos moves e2e4 e2e4 e7e5"]);
+        assert_eq!(uci.board.to_fen(), "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1");
+    }
+
+    #[test]
+    fn keeps_the_position_on_a_bad_fen() {
+        let uci = uci_after(&["position startpos moves d2d4", "position fen 4k3/8/8/8/8/8/8/4K3 x - - 0 1"]);
+        assert_eq!(uci.board.to_fen(), "rnbqkbnr/pppppppp/8/8/3P4/8/PPP1PPPP/RNBQKBNR b KQkq - 0 1");
+
+        let uci = uci_after(&["position startpos moves d2d4", "position"]);
+        assert_eq!(uci.board.to_fen(), "rnbqkbnr/pppppppp/8/8/3P4/8/PPP1PPPP/RNBQKBNR b KQkq - 0 1");
+    }
+
+    #[test]
+    fn sets_the_hash_size() {
+        let mut uci = Uci::new();
+        let tt = Arc::clone(&uci.tt);
+        uci.handle_command("setoption name Hash value x");
+        assert!(Arc::ptr_eq(&uci.tt, &tt));
+
+        uci.handle_command("setoption name Hash value 1");
+        assert!(!Arc::ptr_eq(&uci.tt, &tt));
+    }
+
+    #[test]
+    fn sets_the_thread_count() {
+        let uci = uci_after(&["setoption name Threads value 4"]);
+        assert_eq!(uci.threads, 4);
+
+        let uci = uci_after(&["setoption name Threads value 0"]);
+        assert_eq!(uci.threads, 1);
+        let uci = uci_after(&["setoption name Threads value 100000"]);
+        assert_eq!(uci.threads, MAX_THREADS);
+        let uci = uci_after(&["setoption name Threads value 3", "setoption name Threads value many"]);
+        assert_eq!(uci.threads, 3);
+    }
+
+    const SWITCHES: [&str; 5] = ["NullMove", "LateMoveReductions", "ReverseFutility", "Futility", "CheckExtensions"];
+
+    // The switches in the same order as their names
+    fn switches(config: &SearchConfig) -> [bool; 5] {
+        [config.null_move, config.late_move_reductions, config.reverse_futility, config.futility, config.check_extensions]
+    }
+
+    #[test]
+    fn sets_each_search_switch() {
+        for (index, name) in SWITCHES.iter().enumerate() {
+            let mut uci = Uci::new();
+            uci.handle_command(&format!("setoption name {} value false", name));
+            let mut expected = [true; 5];
+            expected[index] = false;
+            assert_eq!(switches(&uci.config), expected, "switching off {}", name);
+
+            uci.handle_command(&format!("setoption name {} value maybe", name));
+            assert_eq!(switches(&uci.config), expected, "{} changed on a bad value", name);
+
+            uci.handle_command(&format!("setoption name {} value true", name));
+            assert_eq!(switches(&uci.config), [true; 5], "switching on {}", name);
+        }
+    }
+}
@@ -6,4 +6,27 @@ pub fn get_rank(square: u8) -> u8 {
 // Helper function to get the file (0 to 7) from a square (0 to 63)
 pub fn get_file(square: u8) -> u8 {
     square % 8
-}
\ No newline at end of file
+}
+
+// Removes the lowest set bit from a bitboard and returns its square
+pub fn pop_lsb(bitboard: &mut u64) -> u8 {
+    let square = bitboard.trailing_zeros() as u8;
+    *bitboard &= *bitboard - 1;
+    square
+}
+
+// Algebraic name of a square, e.g. 28 -> "e4"
+pub fn square_name(square: u8) -> String {
+    let file = (b'a' + get_file(square)) as char;
+    let rank = (b'1' + get_rank(square)) as char;
+    format!("{}{}", file, rank)
+}
+
+// Parses an algebraic square name such as "e4", returning None if it isn't one
+pub fn parse_square(name: &str) -> Option<u8> {
+    let bytes = name.as_bytes();
+    if bytes.len() != 2 || !(b'a'..=b'h').contains(&bytes[0]) || !(b'1'..=b'8').contains(&bytes[1]) {
+        return None;
+    }
+    Some((bytes[1] - b'1') * 8 + (bytes[0] - b'a'))
+}
@@ -0,0 +1,193 @@
+// zobrist.rs
+// Zobrist keys for hashing positions. The keys are generated at compile time from a fixed seed,
+// so hashes are stable between runs and can be compared across threads or saved to disk.
+use crate::board::{side_index, Board, BLACK, WHITE};
+use crate::piece::PieceType;
+use crate::utils::pop_lsb;
+
+// One key per piece type, colour and square: white pieces first, then black
+pub const PIECE_KEYS: [[u64; 64]; 12] = generate_piece_keys();
+
+// Xored in when black is to move
+pub const SIDE_KEY: u64 = random_key(12 * 64);
+
+// One key per value of the 4-bit castling rights
+pub const CASTLING_KEYS: [u64; 16] = generate_keys::<16>(12 * 64 + 1);
+
+// One key per en passant file
+pub const EN_PASSANT_KEYS: [u64; 8] = generate_keys::<8>(12 * 64 + 17);
+
+// SplitMix64, good enough to spread the bits of a counter into a random-looking key
+const fn random_key(index: u64) -> u64 {
+    let mut z = (index + 1).wrapping_mul(0x9E3779B97F4A7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    z ^ (z >> 31)
+}
+
+const fn generate_keys<const N: usize>(offset: u64) -> [u64; N] {
+    let mut keys = [0u64; N];
+    let mut i = 0;
+    while i < N {
+        keys[i] = random_key(offset + i as u64);
+        i += 1;
+    }
+    keys
+}
+
+const fn generate_piece_keys() -> [[u64; 64]; 12] {
+    let mut keys = [[0u64; 64]; 12];
+    let mut piece = 0;
+    while piece < 12 {
+        let mut square = 0;
+        while square < 64 {
+            keys[piece][square] = random_key((piece * 64 + square) as u64);
+            square += 1;
+        }
+        piece += 1;
+    }
+    keys
+}
+
+// Key for a piece of the given side standing on a square
+pub fn piece_key(piece_type: PieceType, side: bool, square: u8) -> u64 {
+    PIECE_KEYS[side_index(side) * 6 + piece_type.index()][square as usize]
+}
+
+// Key for the en passant target square, which only depends on its file
+pub fn en_passant_key(square: u8) -> u64 {
+    EN_PASSANT_KEYS[(square % 8) as usize]
+}
+
+impl Board {
+    // Computes the Zobrist hash of the position from scratch.
+    // make_move and unmake_move keep `hash` up to date incrementally; this is the reference they are checked against.
+    pub fn compute_hash(&self) -> u64 {
+        let mut hash = 0;
+
+        for square in 0..64 {
+            if let Some((piece_type, side)) = self.piece_at(square) {
+                hash ^= piece_key(piece_type, side, square);
+            }
+        }
+
+        if self.side_to_move == BLACK {
+            hash ^= SIDE_KEY;
+        }
+
+        hash ^= CASTLING_KEYS[self.castling_rights as usize];
+
+        if let Some(en_passant) = self.en_passant {
+            hash ^= en_passant_key(en_passant);
+        }
+
+        hash
+    }
+
+    // Computes the pawn key from scratch: the piece keys of the pawns, and nothing else
+    pub fn compute_pawn_key(&self) -> u64 {
+        let mut key = 0;
+
+        for side in [WHITE, BLACK] {
+            let mut pawns = self.pieces(PieceType::Pawn, side);
+            while pawns != 0 {
+                key ^= piece_key(PieceType::Pawn, side, pop_lsb(&mut pawns));
+            }
+        }
+
+        key
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::get_file;
+
+    // How many moves of each special kind the walk made
+    #[derive(Default)]
+    struct Seen {
+        castles: usize,
+        en_passants: usize,
+        promotions: usize,
+        null_moves: usize,
+    }
+
+    fn check_keys(board: &Board) {
+        assert_eq!(board.hash, board.compute_hash(), "hash of {}", board.to_fen());
+        assert_eq!(board.pawn_key, board.compute_pawn_key(), "pawn key of {}", board.to_fen());
+    }
+
+    // Makes and unmakes every move down to the given depth, and a null move in every position not
+    // in check, checking the incremental keys against ones computed from scratch each time
+    fn walk(board: &mut Board, depth: usize, seen: &mut Seen) {
+        if depth == 0 {
+            return;
+        }
+        let (hash, pawn_key) = (board.hash, board.pawn_key);
+
+        for mv in board.generate_legal_moves() {
+            if mv.piece_type == PieceType::King && get_file(mv.from).abs_diff(get_file(mv.to)) == 2 {
+                seen.castles += 1;
+            }
+            if mv.piece_type == PieceType::Pawn && board.en_passant == Some(mv.to) {
+                seen.en_passants += 1;
+            }
+            if mv.promotion.is_some() {
+                seen.promotions += 1;
+            }
+
+            let undo_state = board.make_move(mv);
+            check_keys(board);
+            walk(board, depth - 1, seen);
+            board.unmake_move(mv, undo_state);
+            assert_eq!((board.hash, board.pawn_key), (hash, pawn_key));
+        }
+
+        if !board.is_in_check(board.side_to_move) {
+            seen.null_moves += 1;
+            let undo_state = board.make_null_move();
+            check_keys(board);
+            board.unmake_null_move(undo_state);
+            assert_eq!((board.hash, board.pawn_key), (hash, pawn_key));
+        }
+    }
+
+    #[test]
+    fn incremental_keys_match_computed_keys() {
+        let mut seen = Seen::default();
+        for fen in [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+        ] {
+            let mut board = Board::from_fen(fen).unwrap();
+            check_keys(&board);
+            walk(&mut board, 3, &mut seen);
+        }
+
+        assert!(seen.castles > 0 && seen.en_passants > 0 && seen.promotions > 0 && seen.null_moves > 0);
+    }
+
+    #[test]
+    fn transpositions_hash_alike() {
+        let mut first = Board::new();
+        let mut second = Board::new();
+        for san in ["Nf3", "d5", "Nc3"] {
+            let mv = first.parse_san(san).unwrap();
+            first.make_move(mv);
+        }
+        for san in ["Nc3", "d5", "Nf3"] {
+            let mv = second.parse_san(san).unwrap();
+            second.make_move(mv);
+        }
+        assert_eq!(first.hash, second.hash);
+        assert_eq!(first.pawn_key, second.pawn_key);
+
+        // Same pieces, other side to move
+        let white = Board::from_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        let black = Board::from_fen("4k3/8/8/8/8/8/8/4K3 b - - 0 1").unwrap();
+        assert_eq!(white.hash ^ SIDE_KEY, black.hash);
+    }
+}
@@ -0,0 +1,56 @@
+// perft.rs
+// Move generation checked against the well-known perft counts, from
+// https://www.chessprogramming.org/Perft_Results
+use rusty_engine::board::Board;
+
+// Number of leaf positions at the given depth
+fn perft(board: &mut Board, depth: usize) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+
+    let mut nodes = 0;
+    for mv in board.generate_legal_moves() {
+        let undo_state = board.make_move(mv);
+        nodes += perft(board, depth - 1);
+        board.unmake_move(mv, undo_state);
+    }
+    nodes
+}
+
+// Checks the counts for depth 1, 2, ... of the position, and that it is left as it was found
+fn check_perft(fen: &str, expected: &[u64]) {
+    let mut board = Board::from_fen(fen).unwrap();
+    for (depth, &nodes) in expected.iter().enumerate() {
+        assert_eq!(perft(&mut board, depth + 1), nodes, "perft({}) of {}", depth + 1, fen);
+    }
+    assert_eq!(board.to_fen(), fen);
+}
+
+#[test]
+fn perft_start_position() {
+    check_perft("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", &[20, 400, 8_902, 197_281, 4_865_609]);
+}
+
+// Castling on both sides, pins, en passant and promotions all at once
+#[test]
+fn perft_kiwipete() {
+    check_perft("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", &[48, 2_039, 97_862, 4_085_603]);
+}
+
+// En passant captures that would expose the king along the rank
+#[test]
+fn perft_en_passant() {
+    check_perft("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", &[14, 191, 2_812, 43_238, 674_624]);
+}
+
+// Promotions, with and without capture, and castling out of a pin
+#[test]
+fn perft_promotions() {
+    check_perft("r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1", &[6, 264, 9_467, 422_333]);
+}
+
+#[test]
+fn perft_promotion_with_check() {
+    check_perft("rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8", &[44, 1_486, 62_379, 2_103_487]);
+}
@@ -0,0 +1,110 @@
+// search.rs
+// Search tests on fixed positions. A single-threaded search to a fixed depth is deterministic, so
+// node counts can be compared between runs.
+use std::sync::Arc;
+
+use rusty_engine::board::Board;
+use rusty_engine::engine::{Engine, SearchConfig, SearchLimits};
+use rusty_engine::score::ScoreKind;
+use rusty_engine::tt::TranspositionTable;
+
+const KIWIPETE: &str = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+
+// Nodes searched to reach the given depth, and the best move found
+fn search_nodes(fen: &str, depth: usize, config: SearchConfig) -> (u64, String) {
+    let mut engine = Engine::with_board(Some(Board::from_fen(fen).unwrap()));
+    engine.set_search_config(config);
+
+    let mut nodes = 0;
+    let best_move = engine.search(&SearchLimits::depth(depth), |info| nodes = info.nodes).unwrap();
+    (nodes, best_move.mv.get_uci())
+}
+
+#[test]
+fn search_is_deterministic() {
+    assert_eq!(search_nodes(KIWIPETE, 5, SearchConfig::default()), search_nodes(KIWIPETE, 5, SearchConfig::default()));
+}
+
+#[test]
+fn single_thread_search_is_deterministic() {
+    let search = || {
+        let mut engine = Engine::with_board(Some(Board::from_fen(KIWIPETE).unwrap()));
+        engine.set_threads(4);
+        engine.set_threads(1);
+        let mut nodes = 0;
+        let best_move = engine.search(&SearchLimits::depth(5), |info| nodes = info.nodes).unwrap();
+        (nodes, best_move.mv.get_uci(), best_move.score)
+    };
+    assert_eq!(search(), search());
+}
+
+// Ra6 bxa6 b7#, found with the helpers filling the table alongside the main thread
+#[test]
+fn parallel_search_finds_mate_in_two() {
+    let board = Board::from_fen("kbK5/pp6/1P6/8/8/8/8/R7 w - - 0 1").unwrap();
+    for threads in [2, 4] {
+        let mut engine = Engine::with_board(Some(board.clone()));
+        engine.set_threads(threads);
+        let best_move = engine.search(&SearchLimits::depth(6), |_| {}).unwrap();
+
+        assert!(board.clone().generate_legal_moves().contains(&best_move.mv));
+        assert_eq!(best_move.mv.get_uci(), "a1a6", "with {} threads", threads);
+        assert_eq!(best_move.score.kind(), ScoreKind::Mate(2), "with {} threads", threads);
+    }
+}
+
+#[test]
+fn every_search_switch_changes_the_tree() {
+    let all = SearchConfig::default();
+    let (all_on, _) = search_nodes(KIWIPETE, 5, all);
+
+    let switched_off = [
+        ("null_move", SearchConfig { null_move: false, ..all }),
+        ("late_move_reductions", SearchConfig { late_move_reductions: false, ..all }),
+        ("reverse_futility", SearchConfig { reverse_futility: false, ..all }),
+        ("futility", SearchConfig { futility: false, ..all }),
+        ("check_extensions", SearchConfig { check_extensions: false, ..all }),
+    ];
+    for (name, config) in switched_off {
+        let (nodes, _) = search_nodes(KIWIPETE, 5, config);
+        assert_ne!(nodes, all_on, "switching off {} searched the same tree", name);
+    }
+}
+
+#[test]
+fn pruning_searches_fewer_nodes_than_full_width() {
+    let (pruned, _) = search_nodes(KIWIPETE, 5, SearchConfig::default());
+    let (full_width, _) = search_nodes(KIWIPETE, 5, SearchConfig::full_width());
+    assert!(pruned < full_width, "{} nodes with pruning, {} without", pruned, full_width);
+}
+
+#[test]
+fn finds_mate_in_one() {
+    let (_, best_move) = search_nodes("k7/3Q4/1K6/8/8/8/8/8 w - - 0 1", 3, SearchConfig::default());
+    assert_eq!(best_move, "d7e8");
+}
+
+// The second search finds the whole tree of the first in the transposition table
+#[test]
+fn principal_variation_survives_the_transposition_table() {
+    let mut engine = Engine::with_board(Some(Board::from_fen(KIWIPETE).unwrap()));
+    for _ in 0..2 {
+        let mut pv_lengths = Vec::new();
+        engine.search(&SearchLimits::depth(6), |info| pv_lengths.push((info.depth, info.pv.len())));
+
+        for (depth, pv_length) in pv_lengths {
+            assert!(pv_length >= depth, "depth {} has a principal variation of only {} moves", depth, pv_length);
+        }
+    }
+}
+
+#[test]
+fn searches_with_the_transposition_table_it_is_given() {
+    let tt = Arc::new(TranspositionTable::new(1));
+    let mut engine = Engine::with_board(Some(Board::from_fen(KIWIPETE).unwrap()));
+    engine.set_transposition_table(Arc::clone(&tt));
+    engine.search(&SearchLimits::depth(4), |_| {});
+
+    assert!(tt.hashfull() > 0);
+    assert!(Arc::ptr_eq(&engine.transposition_table(), &tt));
+}
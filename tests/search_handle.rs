@@ -0,0 +1,86 @@
+// search_handle.rs
+// Searches running in the background, driven the way a front-end drives them
+use std::thread;
+use std::time::Duration;
+
+use rusty_engine::board::Board;
+use rusty_engine::engine::{Engine, SearchLimits};
+use rusty_engine::search::{SearchEvent, SearchHandle};
+
+fn infinite() -> SearchLimits {
+    SearchLimits { infinite: true, ..SearchLimits::default() }
+}
+
+// Every event of the search, in the order they arrived
+fn events(handle: &SearchHandle) -> Vec<SearchEvent> {
+    let mut events = Vec::new();
+    while let Some(event) = handle.recv() {
+        events.push(event);
+    }
+    events
+}
+
+// The events that already arrived, without waiting for more
+fn events_so_far(handle: &SearchHandle) -> Vec<SearchEvent> {
+    let mut events = Vec::new();
+    while let Some(event) = handle.try_recv() {
+        events.push(event);
+    }
+    events
+}
+
+#[test]
+fn infinite_search_finishes_only_after_stop() {
+    let board = Board::new();
+    let handle = SearchHandle::start(Engine::with_board(Some(board.clone())), infinite());
+
+    thread::sleep(Duration::from_millis(200));
+    assert!(!handle.is_finished());
+    assert!(!events_so_far(&handle).iter().any(|event| matches!(event, SearchEvent::Finished(_))));
+
+    handle.stop();
+    let events = events(&handle);
+    let Some(SearchEvent::Finished(Some(best_move))) = events.last() else {
+        panic!("the search ended without a best move: {:?}", events.last());
+    };
+    assert!(board.clone().generate_legal_moves().contains(&best_move.mv));
+    handle.join();
+}
+
+// A search that runs out of depth still waits for stop when it is infinite
+#[test]
+fn infinite_search_waits_for_stop_after_the_last_depth() {
+    let engine = Engine::with_board(Some(Board::from_fen("k7/3Q4/1K6/8/8/8/8/8 w - - 0 1").unwrap()));
+    let handle = SearchHandle::start(engine, SearchLimits { depth: Some(2), ..infinite() });
+
+    thread::sleep(Duration::from_millis(100));
+    assert!(!handle.is_finished());
+    handle.stop();
+    handle.join();
+}
+
+#[test]
+fn events_arrive_by_increasing_depth_and_end_with_the_best_move() {
+    let handle = SearchHandle::start(Engine::with_board(Some(Board::new())), SearchLimits::depth(6));
+    let events = events(&handle);
+
+    let depths: Vec<usize> = events.iter().filter_map(|event| match event {
+        SearchEvent::Info(info) => Some(info.depth),
+        SearchEvent::Finished(_) => None,
+    }).collect();
+    assert_eq!(depths, (1..=6).collect::<Vec<usize>>());
+
+    assert!(matches!(events.last(), Some(SearchEvent::Finished(Some(_)))));
+    assert_eq!(events.iter().filter(|event| matches!(event, SearchEvent::Finished(_))).count(), 1);
+    handle.join();
+}
+
+#[test]
+fn dropping_a_running_search_stops_it() {
+    let handle = SearchHandle::start(Engine::with_board(Some(Board::new())), infinite());
+    thread::sleep(Duration::from_millis(50));
+    drop(handle);
+
+    let handle = SearchHandle::start_with_callback(Engine::with_board(Some(Board::new())), infinite(), |_| {});
+    drop(handle);
+}